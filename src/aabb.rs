@@ -0,0 +1,128 @@
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+// The thinnest a padded box gets. Flat objects like rectangles, and triangles
+// lying in an axis-aligned plane, would otherwise get boxes of zero thickness.
+pub const MIN_EXTENT: f64 = 1e-4;
+
+// Axis-aligned bounding box
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn surrounding(a: Self, b: Self) -> Self {
+        Self {
+            min: a.min.min(b.min),
+            max: a.max.max(b.max),
+        }
+    }
+
+    // Grows any dimension thinner than MIN_EXTENT, so that flat objects still
+    // get a box which rays can hit.
+    pub fn padded(self) -> Self {
+        let extent = self.max - self.min;
+        let padding = |extent: f64| {
+            if extent < MIN_EXTENT {
+                (MIN_EXTENT - extent) / 2.0
+            } else {
+                0.0
+            }
+        };
+        let padding = Vec3(padding(extent.0), padding(extent.1), padding(extent.2));
+        Self {
+            min: self.min - padding,
+            max: self.max + padding,
+        }
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let extent = self.max - self.min;
+        2.0 * (extent.0 * extent.1 + extent.1 * extent.2 + extent.2 * extent.0)
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let extent = self.max - self.min;
+        if extent.0 > extent.1 && extent.0 > extent.2 {
+            0
+        } else if extent.1 > extent.2 {
+            1
+        } else {
+            2
+        }
+    }
+
+    // The "slab" method - for each axis, find the interval of t over which the
+    // ray is between the two planes bounding the box on that axis. The ray
+    // hits the box if and only if those intervals all overlap.
+    pub fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> bool {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for axis in 0..3 {
+            let inverse_direction = 1.0 / ray.vector[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inverse_direction;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inverse_direction;
+            if inverse_direction < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box() -> Aabb {
+        Aabb::new(Vec3(-1.0, -1.0, -1.0), Vec3(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn ray_through_box_hits() {
+        let ray = Ray::new(Vec3(0.0, 0.0, -5.0), Vec3(0.0, 0.0, 1.0));
+        assert!(unit_box().hit(ray, 0.0, f64::INFINITY));
+    }
+
+    #[test]
+    fn ray_beside_box_misses() {
+        let ray = Ray::new(Vec3(2.0, 0.0, -5.0), Vec3(0.0, 0.0, 1.0));
+        assert!(!unit_box().hit(ray, 0.0, f64::INFINITY));
+    }
+
+    #[test]
+    fn ray_pointing_away_from_box_misses() {
+        let ray = Ray::new(Vec3(0.0, 0.0, -5.0), Vec3(0.0, 0.0, -1.0));
+        assert!(!unit_box().hit(ray, 0.0, f64::INFINITY));
+    }
+
+    #[test]
+    fn box_beyond_t_max_misses() {
+        let ray = Ray::new(Vec3(0.0, 0.0, -5.0), Vec3(0.0, 0.0, 1.0));
+        assert!(!unit_box().hit(ray, 0.0, 3.0));
+    }
+
+    #[test]
+    fn can_get_surrounding_box() {
+        let a = Aabb::new(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 1.0, 1.0));
+        let b = Aabb::new(Vec3(-1.0, 0.5, 0.5), Vec3(0.5, 2.0, 0.5));
+        let surrounding = Aabb::surrounding(a, b);
+        assert_eq!(surrounding.min, Vec3(-1.0, 0.0, 0.0));
+        assert_eq!(surrounding.max, Vec3(1.0, 2.0, 1.0));
+    }
+}
@@ -0,0 +1,103 @@
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::utils::{lerp, remap};
+use serde::Deserialize;
+use std::str::FromStr;
+
+// What rays see when they don't hit anything. This is the only light in a
+// scene without emissive materials.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Background {
+    // white at the horizon, fading to blue overhead
+    #[default]
+    Sky,
+    Solid {
+        color: [f64; 3],
+    },
+}
+
+impl Background {
+    pub fn color(&self, ray: Ray) -> Color {
+        match self {
+            Self::Sky => {
+                let direction = ray.vector.unit_vector();
+                let upwardsness = remap(direction.y(), &(-1.0..1.0), &(0.0..1.0));
+                Color::from_vec(lerp(upwardsness, Color::white().vec, Color::sky_blue().vec))
+            }
+            Self::Solid { color } => Color::new(color[0], color[1], color[2]),
+        }
+    }
+}
+
+// Accepts "sky", "black", or a color as "r,g,b".
+impl FromStr for Background {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "sky" => return Ok(Self::Sky),
+            "black" => return Ok(Self::Solid { color: [0.0; 3] }),
+            _ => {}
+        }
+        let components: Vec<f64> = value
+            .split(',')
+            .map(|component| component.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("invalid background '{}'", value))?;
+        match components[..] {
+            [r, g, b] if components.iter().all(|c| c.is_finite() && *c >= 0.0) => {
+                Ok(Self::Solid { color: [r, g, b] })
+            }
+            _ => Err(format!(
+                "invalid background '{}' (expected sky, black or r,g,b)",
+                value
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Vec3;
+
+    fn ray(direction: Vec3) -> Ray {
+        Ray::new(Vec3(0.0, 0.0, 0.0), direction)
+    }
+
+    #[test]
+    fn sky_fades_from_white_to_blue() {
+        let sky = Background::Sky;
+        assert_eq!(sky.color(ray(Vec3(0.0, -1.0, 0.0))).vec, Color::white().vec);
+        assert_eq!(
+            sky.color(ray(Vec3(0.0, 1.0, 0.0))).vec,
+            Color::sky_blue().vec
+        );
+    }
+
+    #[test]
+    fn solid_is_the_same_everywhere() {
+        let solid = Background::Solid {
+            color: [0.1, 0.2, 0.3],
+        };
+        for direction in [Vec3(0.0, 1.0, 0.0), Vec3(1.0, -2.0, 0.5)] {
+            assert_eq!(solid.color(ray(direction)).vec, Vec3(0.1, 0.2, 0.3));
+        }
+    }
+
+    #[test]
+    fn parses_from_string() {
+        assert_eq!("sky".parse(), Ok(Background::Sky));
+        assert_eq!("black".parse(), Ok(Background::Solid { color: [0.0; 3] }));
+        assert_eq!(
+            "0.5, 1,2".parse(),
+            Ok(Background::Solid {
+                color: [0.5, 1.0, 2.0]
+            })
+        );
+        assert!("1,2".parse::<Background>().is_err());
+        assert!("-1,0,0".parse::<Background>().is_err());
+        assert!("night".parse::<Background>().is_err());
+    }
+}
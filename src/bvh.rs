@@ -0,0 +1,299 @@
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable};
+use crate::hittable_list::HittableList;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use std::sync::Arc;
+
+enum BvhContents {
+    Empty,
+    Leaf(Arc<dyn Hittable>),
+    Node {
+        left: Box<BvhNode>,
+        right: Box<BvhNode>,
+    },
+}
+
+// Bounding volume hierarchy - a binary tree of bounding boxes, so that a ray
+// only needs to be tested against the objects whose boxes it passes through.
+pub struct BvhNode {
+    bounding_box: Aabb,
+    contents: BvhContents,
+    // Objects without a bounding box, like infinite planes, which every ray
+    // has to be tested against. Only ever non-empty at the root.
+    unbounded: Vec<Arc<dyn Hittable>>,
+}
+
+impl BvhNode {
+    pub fn new(list: &HittableList) -> Self {
+        let mut bounded = vec![];
+        let mut unbounded = vec![];
+        for object in list.objects() {
+            match object.bounding_box() {
+                Some(bounding_box) => bounded.push((Arc::clone(object), bounding_box)),
+                None => unbounded.push(Arc::clone(object)),
+            }
+        }
+        Self {
+            unbounded,
+            ..Self::build(bounded)
+        }
+    }
+
+    fn build(mut objects: Vec<(Arc<dyn Hittable>, Aabb)>) -> Self {
+        match objects.len() {
+            0 => Self {
+                bounding_box: Aabb::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0)),
+                contents: BvhContents::Empty,
+                unbounded: vec![],
+            },
+            1 => {
+                let (object, bounding_box) = objects.pop().unwrap();
+                Self {
+                    bounding_box,
+                    contents: BvhContents::Leaf(object),
+                    unbounded: vec![],
+                }
+            }
+            _ => {
+                let centroid_bounds = objects
+                    .iter()
+                    .map(|(_, bounding_box)| {
+                        let centroid = bounding_box.centroid();
+                        Aabb::new(centroid, centroid)
+                    })
+                    .reduce(Aabb::surrounding)
+                    .unwrap();
+                let axis = centroid_bounds.longest_axis();
+                objects.sort_by(|(_, a), (_, b)| a.centroid()[axis].total_cmp(&b.centroid()[axis]));
+                let right_objects = objects.split_off(sah_split_index(&objects));
+                let left = Self::build(objects);
+                let right = Self::build(right_objects);
+                Self {
+                    bounding_box: Aabb::surrounding(left.bounding_box, right.bounding_box),
+                    contents: BvhContents::Node {
+                        left: Box::new(left),
+                        right: Box::new(right),
+                    },
+                    unbounded: vec![],
+                }
+            }
+        }
+    }
+}
+
+impl BvhNode {
+    fn hit_tree(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        match &self.contents {
+            BvhContents::Empty => None,
+            BvhContents::Leaf(object) => object.hit(ray, t_min, t_max),
+            BvhContents::Node { left, right } => {
+                if !self.bounding_box.hit(ray, t_min, t_max) {
+                    return None;
+                }
+                let left_hit = left.hit_tree(ray, t_min, t_max);
+                let closest_so_far = left_hit.as_ref().map_or(t_max, |hit| hit.ray_t);
+                right.hit_tree(ray, t_min, closest_so_far).or(left_hit)
+            }
+        }
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let mut closest_hit = self.hit_tree(ray, t_min, t_max);
+        for object in &self.unbounded {
+            let closest_so_far = closest_hit.as_ref().map_or(t_max, |hit| hit.ray_t);
+            if let Some(hit) = object.hit(ray, t_min, closest_so_far) {
+                closest_hit = Some(hit);
+            }
+        }
+        closest_hit
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        match self.contents {
+            BvhContents::Empty => None,
+            _ if !self.unbounded.is_empty() => None,
+            _ => Some(self.bounding_box),
+        }
+    }
+}
+
+// Surface area heuristic - the chance of a ray hitting a child box is roughly
+// proportional to its surface area, so the cost of a split is estimated as the
+// sum over both sides of the box's surface area times the number of objects it
+// holds. The objects must already be sorted along the split axis. Returns the
+// index of the first object to go on the right hand side.
+fn sah_split_index(objects: &[(Arc<dyn Hittable>, Aabb)]) -> usize {
+    let object_count = objects.len();
+    let mut right_areas = vec![0.0; object_count];
+    let mut right_box = objects[object_count - 1].1;
+    for idx in (1..object_count).rev() {
+        right_box = Aabb::surrounding(right_box, objects[idx].1);
+        right_areas[idx] = right_box.surface_area();
+    }
+
+    let mut best_cost = f64::INFINITY;
+    let mut best_idx = object_count / 2;
+    let mut left_box = objects[0].1;
+    for (idx, right_area) in right_areas.iter().enumerate().skip(1) {
+        let cost = left_box.surface_area() * idx as f64 + right_area * (object_count - idx) as f64;
+        if cost < best_cost {
+            best_cost = cost;
+            best_idx = idx;
+        }
+        left_box = Aabb::surrounding(left_box, objects[idx].1);
+    }
+    best_idx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::plane::Plane;
+    use crate::rect::{AxisRect, Cuboid, RectPlane};
+    use crate::sphere::{MovingSphere, ObjectSphere};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn random_vec(rng: &mut StdRng, min: f64, max: f64) -> Vec3 {
+        Vec3(
+            rng.gen_range(min..max),
+            rng.gen_range(min..max),
+            rng.gen_range(min..max),
+        )
+    }
+
+    fn random_world(rng: &mut StdRng, sphere_count: usize) -> HittableList {
+        let mut world = HittableList::new();
+        for _ in 0..sphere_count {
+            world.add(ObjectSphere::new(
+                rng.gen_range(0.1..2.0),
+                random_vec(rng, -20.0, 20.0),
+                Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+            ));
+        }
+        world
+    }
+
+    fn assert_matches_linear_list(world: &HittableList, rng: &mut StdRng) {
+        let bvh = BvhNode::new(world);
+        let mut hit_count = 0;
+        for _ in 0..10_000 {
+            let ray = Ray::new(random_vec(rng, -30.0, 30.0), random_vec(rng, -1.0, 1.0))
+                .with_time(rng.gen());
+            let list_hit = world.hit(ray, 0.001, f64::INFINITY);
+            let bvh_hit = bvh.hit(ray, 0.001, f64::INFINITY);
+            match (list_hit, bvh_hit) {
+                (None, None) => {}
+                (Some(list_hit), Some(bvh_hit)) => {
+                    hit_count += 1;
+                    assert_eq!(list_hit.ray_t, bvh_hit.ray_t);
+                    assert_eq!(list_hit.hit_point, bvh_hit.hit_point);
+                    assert_eq!(list_hit.normal, bvh_hit.normal);
+                }
+                _ => panic!("bvh and list disagree about whether {:?} hits", ray),
+            }
+        }
+        // make sure the test is actually exercising some hits
+        assert!(hit_count > 1000);
+    }
+
+    #[test]
+    fn bvh_gives_same_closest_hit_as_linear_list() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let world = random_world(&mut rng, 500);
+        assert_matches_linear_list(&world, &mut rng);
+    }
+
+    #[test]
+    fn bvh_handles_flat_and_unbounded_objects() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut world = random_world(&mut rng, 100);
+        let material = Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5)));
+        for plane in [RectPlane::Xy, RectPlane::Xz, RectPlane::Yz] {
+            world.add(AxisRect::new(
+                plane,
+                (-5.0, 5.0),
+                (0.0, 8.0),
+                3.0,
+                material.clone(),
+            ));
+        }
+        world.add(Cuboid::new(
+            Vec3(-8.0, -8.0, -8.0),
+            Vec3(-2.0, 1.0, 4.0),
+            material.clone(),
+        ));
+        world.add(Plane::new(
+            Vec3(0.0, -15.0, 0.0),
+            Vec3(0.0, 1.0, 0.0),
+            material.clone(),
+        ));
+        world.add(Plane::new(
+            Vec3(18.0, 0.0, 0.0),
+            Vec3(-1.0, 0.2, 0.0),
+            material,
+        ));
+        assert!(BvhNode::new(&world).bounding_box().is_none());
+        assert_matches_linear_list(&world, &mut rng);
+    }
+
+    #[test]
+    fn bvh_finds_negative_radius_spheres() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut world = random_world(&mut rng, 200);
+        for _ in 0..100 {
+            world.add(ObjectSphere::new(
+                -rng.gen_range(0.1..2.0),
+                random_vec(&mut rng, -20.0, 20.0),
+                Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+            ));
+        }
+        assert_matches_linear_list(&world, &mut rng);
+    }
+
+    #[test]
+    fn bvh_finds_moving_spheres_all_along_their_paths() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut world = random_world(&mut rng, 200);
+        for _ in 0..300 {
+            let start = random_vec(&mut rng, -20.0, 20.0);
+            world.add(MovingSphere::new(
+                rng.gen_range(0.1..2.0),
+                start,
+                start + random_vec(&mut rng, -5.0, 5.0),
+                (0.0, 1.0),
+                Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+            ));
+        }
+        assert_matches_linear_list(&world, &mut rng);
+    }
+
+    #[test]
+    fn empty_bvh_is_never_hit() {
+        let bvh = BvhNode::new(&HittableList::new());
+        let ray = Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0));
+        assert!(bvh.hit(ray, 0.0, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn bvh_respects_t_max() {
+        let mut world = HittableList::new();
+        world.add(ObjectSphere::new(
+            1.0,
+            Vec3(0.0, 0.0, -10.0),
+            Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+        ));
+        world.add(ObjectSphere::new(
+            1.0,
+            Vec3(0.0, 0.0, -20.0),
+            Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+        ));
+        let bvh = BvhNode::new(&world);
+        let ray = Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, -1.0));
+        assert_eq!(bvh.hit(ray, 0.0, f64::INFINITY).unwrap().ray_t, 9.0);
+        assert!(bvh.hit(ray, 0.0, 5.0).is_none());
+    }
+}
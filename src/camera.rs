@@ -1,5 +1,40 @@
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::utils::lerp;
 use crate::vec3::Vec3;
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4};
+
+// Where the camera is and how it's set up, independent of the size of the
+// image being rendered.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraSettings {
+    pub aspect_ratio: f64,
+    pub vertical_fov_degrees: f64,
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    pub view_up: Vec3,
+    pub lens_radius: f64,
+    pub focus_dist: f64,
+    // rays are sent at random times between these, for motion blur
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+}
+
+impl CameraSettings {
+    pub fn camera(&self, image_width: u32) -> Camera {
+        Camera::new(
+            image_width,
+            self.aspect_ratio,
+            self.vertical_fov_degrees,
+            self.look_from,
+            self.look_at,
+            self.view_up,
+            self.lens_radius,
+            self.focus_dist,
+        )
+        .with_shutter(self.shutter_open, self.shutter_close)
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct Camera {
@@ -8,23 +43,42 @@ pub struct Camera {
     image_bottom_left: Vec3,
     origin: Vec3,
     lens_radius: f64,
+    shutter_open: f64,
+    shutter_close: f64,
     u: Vec3,
     v: Vec3,
     pub image_height: u32,
     pub image_width: u32,
-    pub viewport_height: f64,
+}
+
+// Maps the unit square evenly onto the unit disk, squares to rings, so that
+// points which are spread out over the square stay spread out over the disk.
+fn concentric_disk_point((x, y): (f64, f64)) -> (f64, f64) {
+    let (a, b) = (2.0 * x - 1.0, 2.0 * y - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (radius, theta) = if a.abs() > b.abs() {
+        (a, FRAC_PI_4 * (b / a))
+    } else {
+        (b, FRAC_PI_2 - FRAC_PI_4 * (a / b))
+    };
+    (radius * theta.cos(), radius * theta.sin())
 }
 
 impl Camera {
-    pub fn get_ray(&self, x_level: f64, y_level: f64) -> Ray {
-        let offset_vec = Vec3::random_in_unit_disk() * self.lens_radius;
-        let offset = self.u * offset_vec.x() + self.v * offset_vec.y();
+    // The point on the lens and the time are taken from the sampler.
+    pub fn get_ray(&self, x_level: f64, y_level: f64, sampler: &mut dyn Sampler) -> Ray {
+        let (lens_x, lens_y) = concentric_disk_point(sampler.get_2d());
+        let offset = (self.u * lens_x + self.v * lens_y) * self.lens_radius;
         let ray_origin = self.origin + offset;
         let ray_image_intersection =
             self.image_bottom_left + self.horizontal * x_level + self.vertical * y_level;
         let ray_vector = ray_image_intersection - ray_origin;
-        Ray::new(ray_origin, ray_vector)
+        let time = lerp(sampler.get_1d(), self.shutter_open, self.shutter_close);
+        Ray::new(ray_origin, ray_vector).with_time(time)
     }
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         image_width: u32,
         image_aspect_ratio: f64,
@@ -61,8 +115,98 @@ impl Camera {
             origin: look_from,
             image_height,
             image_width,
-            viewport_height,
             lens_radius,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+        }
+    }
+
+    // By default the shutter is only open for an instant, at time 0.
+    pub fn with_shutter(self, shutter_open: f64, shutter_close: f64) -> Self {
+        Self {
+            shutter_open,
+            shutter_close,
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+
+    fn camera() -> Camera {
+        Camera::new(
+            100,
+            1.0,
+            90.0,
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(0.0, 0.0, -1.0),
+            Vec3(0.0, 1.0, 0.0),
+            0.0,
+            1.0,
+        )
+    }
+
+    #[test]
+    fn rays_are_sent_at_time_zero_by_default() {
+        let mut sampler = IndependentSampler::new(0);
+        assert_eq!(camera().get_ray(0.5, 0.5, &mut sampler).time, 0.0);
+    }
+
+    #[test]
+    fn rays_are_sent_while_the_shutter_is_open() {
+        let camera = camera().with_shutter(1.0, 1.5);
+        let mut sampler = IndependentSampler::new(0);
+        let times: Vec<f64> = (0..1000)
+            .map(|_| camera.get_ray(0.5, 0.5, &mut sampler).time)
+            .collect();
+        assert!(times.iter().all(|time| (1.0..1.5).contains(time)));
+        // spread over the whole interval
+        assert!(times.iter().any(|time| *time < 1.1));
+        assert!(times.iter().any(|time| *time > 1.4));
+    }
+
+    #[test]
+    fn lens_points_cover_the_whole_lens() {
+        let mut sampler = IndependentSampler::new(0);
+        let mut quadrants = [0; 4];
+        for _ in 0..10_000 {
+            let (x, y) = concentric_disk_point(sampler.get_2d());
+            assert!(x * x + y * y <= 1.0 + 1e-9);
+            quadrants[(x < 0.0) as usize * 2 + (y < 0.0) as usize] += 1;
+        }
+        assert!(
+            quadrants.iter().all(|count| *count > 2000),
+            "{:?}",
+            quadrants
+        );
+        // the middle of the square is the middle of the disk, and the middle
+        // of its right edge is the right of the disk
+        assert_eq!(concentric_disk_point((0.5, 0.5)), (0.0, 0.0));
+        assert_eq!(concentric_disk_point((1.0, 0.5)), (1.0, 0.0));
+    }
+
+    #[test]
+    fn rays_start_on_the_lens() {
+        let camera = Camera::new(
+            100,
+            1.0,
+            90.0,
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(0.0, 0.0, -1.0),
+            Vec3(0.0, 1.0, 0.0),
+            0.5,
+            1.0,
+        );
+        let mut sampler = IndependentSampler::new(0);
+        for _ in 0..1000 {
+            let ray = camera.get_ray(0.5, 0.5, &mut sampler);
+            assert_eq!(ray.origin.2, 0.0);
+            assert!(ray.origin.length() <= 0.5 + 1e-9);
+            // all focused on the same point
+            assert!((ray.at(1.0) - Vec3(0.0, 0.0, -1.0)).length() < 1e-9);
         }
     }
 }
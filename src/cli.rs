@@ -0,0 +1,561 @@
+use rtiow::background::Background;
+use rtiow::output::{OutputFormat, PpmEncoding};
+use rtiow::sampler::SamplerKind;
+use rtiow::tonemap::ToneMapOperator;
+use rtiow::Scene;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+pub const HELP: &str = "\
+Render a scene with a path tracer.
+
+USAGE:
+    rtiow [OPTIONS]
+
+OPTIONS:
+    --scene <file>       TOML scene description to render. Without this, a
+                         random field of spheres is rendered.
+    --output <path>      File to write the image to. The format is inferred
+                         from the extension: .ppm or .png for display, or
+                         .pfm, .hdr or .exr for linear high dynamic range
+                         output. Defaults to writing a PPM image to stdout.
+    --sample-map <path>  Also write a grey image of how many samples each
+                         pixel got, white for the most. The format is
+                         inferred from the extension, as for --output.
+    --binary             Write PPM images in the compact binary P6 format
+                         rather than plain text P3.
+    --bit-depth <bits>   Bits per channel of the output image: 8 or 16 for
+                         PNG (default 8), 16 or 32 for OpenEXR (default 16).
+    --tonemap <operator> How to map radiance to the displayable range for
+                         PPM and PNG output: clamp, reinhard,
+                         reinhard-extended, hable or aces. Overrides the
+                         scene's setting, which defaults to clamp.
+    --exposure <stops>   Brightens (or darkens, if negative) the image before
+                         tone mapping. Overrides the scene's setting.
+    --white-point <x>    Radiance that becomes white with reinhard-extended.
+                         Overrides the scene's setting, which defaults to 4.
+    --width <pixels>     Width of the image. Overrides the scene's setting.
+    --aspect <ratio>     Width divided by height. Overrides the scene's
+                         camera setting.
+    --spp <count>        Samples per pixel, or with adaptive sampling the
+                         most any pixel gets. Overrides the scene's setting.
+    --adaptive-threshold <error>
+                         Sample adaptively, stopping once the relative
+                         error of a pixel's brightness is below this, e.g.
+                         0.01. Overrides the scene's setting, which defaults
+                         to not sampling adaptively.
+    --min-spp <count>    Samples every pixel gets with adaptive sampling.
+                         Overrides the scene's setting, which defaults to 16.
+    --max-depth <count>  Maximum number of bounces per ray. Overrides the
+                         scene's setting.
+    --roulette-depth <count>
+                         Bounces after which paths may be ended early by
+                         Russian roulette. Overrides the scene's setting,
+                         which defaults to 5.
+    --sampler <name>     How the random numbers for each sample are chosen:
+                         independent, stratified, halton or sobol.
+                         Overrides the scene's setting, which defaults to
+                         sobol.
+    --background <bg>    What rays that miss everything see: sky, black, or a
+                         constant color as r,g,b. Overrides the scene's
+                         setting, which defaults to sky.
+    --threads <count>    Number of threads to render with. Defaults to the
+                         number of CPUs.
+    --tile-size <pixels> Size of the square tiles the threads take turns to
+                         render. Defaults to 32.
+    --seed <number>      Seed for the random numbers used to render, and to
+                         generate the random scene. Overrides the scene's
+                         setting, which defaults to 0.
+    --progressive        Render the whole image at one sample per pixel,
+                         then keep adding samples in passes, rewriting the
+                         output file after each one, so the render can be
+                         stopped once it looks good enough. Needs --output.
+    --quiet              Don't display progress.
+    --help               Print this message.
+";
+
+#[derive(Debug, PartialEq)]
+pub struct CliError(pub String);
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Options {
+    pub width: Option<u32>,
+    pub aspect: Option<f64>,
+    pub spp: Option<u32>,
+    pub adaptive_threshold: Option<f64>,
+    pub min_spp: Option<u32>,
+    pub max_depth: Option<u32>,
+    pub roulette_depth: Option<u32>,
+    pub sampler: Option<SamplerKind>,
+    pub background: Option<Background>,
+    pub threads: Option<usize>,
+    pub tile_size: Option<u32>,
+    pub seed: Option<u64>,
+    pub scene: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub sample_map: Option<PathBuf>,
+    pub bit_depth: Option<u32>,
+    pub tone_map: Option<ToneMapOperator>,
+    pub exposure: Option<f64>,
+    pub white_point: Option<f64>,
+    pub binary: bool,
+    pub progressive: bool,
+    pub quiet: bool,
+}
+
+impl Options {
+    pub fn output_format(&self) -> OutputFormat {
+        // already validated when parsing
+        self.try_output_format().unwrap()
+    }
+
+    fn try_output_format(&self) -> Result<OutputFormat, String> {
+        let format = match &self.output {
+            Some(path) => OutputFormat::from_path(path)?,
+            None => OutputFormat::Ppm(PpmEncoding::Ascii),
+        };
+        let format = match format {
+            OutputFormat::Ppm(_) if self.binary => OutputFormat::Ppm(PpmEncoding::Binary),
+            _ if self.binary => return Err("--binary only applies to PPM output".to_string()),
+            _ => format,
+        };
+        match self.bit_depth {
+            Some(bits) => format.with_bit_depth(bits),
+            None => Ok(format),
+        }
+    }
+
+    // Overrides the scene's own settings with any given on the command line.
+    pub fn apply(&self, scene: &mut Scene) {
+        if let Some(width) = self.width {
+            scene.settings.image_width = width;
+        }
+        if let Some(aspect) = self.aspect {
+            scene.camera_settings.aspect_ratio = aspect;
+        }
+        if let Some(spp) = self.spp {
+            scene.settings.samples_per_pixel = spp;
+        }
+        if let Some(threshold) = self.adaptive_threshold {
+            scene.settings.adaptive_threshold = Some(threshold);
+        }
+        if let Some(min_spp) = self.min_spp {
+            scene.settings.min_samples_per_pixel = min_spp;
+        }
+        if let Some(max_depth) = self.max_depth {
+            scene.settings.max_depth = max_depth;
+        }
+        if let Some(roulette_depth) = self.roulette_depth {
+            scene.settings.roulette_depth = roulette_depth;
+        }
+        if let Some(sampler) = self.sampler {
+            scene.settings.sampler = sampler;
+        }
+        if let Some(seed) = self.seed {
+            scene.settings.seed = seed;
+        }
+        if let Some(background) = self.background {
+            scene.settings.background = background;
+        }
+        if let Some(operator) = self.tone_map {
+            scene.tone_mapping.operator = operator;
+        }
+        if let Some(exposure) = self.exposure {
+            scene.tone_mapping.exposure = exposure;
+        }
+        if let Some(white_point) = self.white_point {
+            scene.tone_mapping.white_point = white_point;
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Render(Box<Options>),
+    Help,
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: &str) -> Result<T, CliError> {
+    value
+        .parse()
+        .map_err(|_| CliError(format!("invalid value '{}' for {}", value, flag)))
+}
+
+fn parse_positive<T: FromStr + PartialOrd + Default>(
+    flag: &str,
+    value: &str,
+) -> Result<T, CliError> {
+    let parsed: T = parse_value(flag, value)?;
+    if parsed > T::default() {
+        Ok(parsed)
+    } else {
+        Err(CliError(format!("{} must be greater than 0", flag)))
+    }
+}
+
+// Parses the arguments, not including the program name. Values can be given
+// either as "--flag value" or "--flag=value".
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, CliError> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg, None),
+        };
+        let takes_value = match flag.as_str() {
+            "--help" | "-h" | "--quiet" | "--binary" | "--progressive" => false,
+            "--width"
+            | "--aspect"
+            | "--spp"
+            | "--max-depth"
+            | "--roulette-depth"
+            | "--seed"
+            | "--threads"
+            | "--tile-size"
+            | "--scene"
+            | "--output"
+            | "--bit-depth"
+            | "--tonemap"
+            | "--exposure"
+            | "--white-point"
+            | "--background"
+            | "--sampler"
+            | "--adaptive-threshold"
+            | "--min-spp"
+            | "--sample-map" => true,
+            _ => return Err(CliError(format!("unknown option '{}'", flag))),
+        };
+        let value = if takes_value {
+            match inline_value.or_else(|| args.next()) {
+                Some(value) => value,
+                None => return Err(CliError(format!("{} needs a value", flag))),
+            }
+        } else if inline_value.is_some() {
+            return Err(CliError(format!("{} doesn't take a value", flag)));
+        } else {
+            String::new()
+        };
+        match flag.as_str() {
+            "--help" | "-h" => return Ok(Command::Help),
+            "--quiet" => options.quiet = true,
+            "--binary" => options.binary = true,
+            "--progressive" => options.progressive = true,
+            "--width" => options.width = Some(parse_positive(&flag, &value)?),
+            "--aspect" => {
+                let aspect: f64 = parse_positive(&flag, &value)?;
+                if !aspect.is_finite() {
+                    return Err(CliError(format!("invalid value '{}' for {}", value, flag)));
+                }
+                options.aspect = Some(aspect);
+            }
+            "--spp" => options.spp = Some(parse_positive(&flag, &value)?),
+            "--max-depth" => options.max_depth = Some(parse_positive(&flag, &value)?),
+            "--roulette-depth" => options.roulette_depth = Some(parse_value(&flag, &value)?),
+            "--threads" => options.threads = Some(parse_positive(&flag, &value)?),
+            "--tile-size" => options.tile_size = Some(parse_positive(&flag, &value)?),
+            "--seed" => options.seed = Some(parse_value(&flag, &value)?),
+            "--scene" => options.scene = Some(PathBuf::from(value)),
+            "--output" => {
+                let path = PathBuf::from(value);
+                OutputFormat::from_path(&path).map_err(CliError)?;
+                options.output = Some(path);
+            }
+            "--bit-depth" => options.bit_depth = Some(parse_value(&flag, &value)?),
+            "--background" => options.background = Some(value.parse().map_err(CliError)?),
+            "--sampler" => options.sampler = Some(value.parse().map_err(CliError)?),
+            "--tonemap" => options.tone_map = Some(value.parse().map_err(CliError)?),
+            "--exposure" => {
+                let exposure: f64 = parse_value(&flag, &value)?;
+                if !exposure.is_finite() {
+                    return Err(CliError(format!("invalid value '{}' for {}", value, flag)));
+                }
+                options.exposure = Some(exposure);
+            }
+            "--adaptive-threshold" => {
+                let threshold: f64 = parse_positive(&flag, &value)?;
+                if !threshold.is_finite() {
+                    return Err(CliError(format!("invalid value '{}' for {}", value, flag)));
+                }
+                options.adaptive_threshold = Some(threshold);
+            }
+            "--min-spp" => options.min_spp = Some(parse_value(&flag, &value)?),
+            "--sample-map" => {
+                let path = PathBuf::from(value);
+                OutputFormat::from_path(&path).map_err(CliError)?;
+                options.sample_map = Some(path);
+            }
+            "--white-point" => {
+                let white_point: f64 = parse_positive(&flag, &value)?;
+                if !white_point.is_finite() {
+                    return Err(CliError(format!("invalid value '{}' for {}", value, flag)));
+                }
+                options.white_point = Some(white_point);
+            }
+            _ => unreachable!(),
+        }
+    }
+    options.try_output_format().map_err(CliError)?;
+    if options.progressive && options.output.is_none() {
+        return Err(CliError("--progressive needs --output".to_string()));
+    }
+    Ok(Command::Render(Box::new(options)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rtiow::output::{BitDepth, ExrPixelType};
+
+    fn parse(args: &[&str]) -> Result<Command, CliError> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn parse_options(args: &[&str]) -> Options {
+        match parse(args) {
+            Ok(Command::Render(options)) => *options,
+            other => panic!("expected options, got {:?}", other),
+        }
+    }
+
+    fn parse_error(args: &[&str]) -> String {
+        parse(args).unwrap_err().0
+    }
+
+    #[test]
+    fn no_args_gives_defaults() {
+        assert_eq!(parse_options(&[]), Options::default());
+    }
+
+    #[test]
+    fn parses_all_options() {
+        let options = parse_options(&[
+            "--width",
+            "640",
+            "--aspect=1.25",
+            "--spp",
+            "8",
+            "--adaptive-threshold",
+            "0.02",
+            "--min-spp",
+            "4",
+            "--max-depth",
+            "5",
+            "--roulette-depth",
+            "0",
+            "--sampler",
+            "stratified",
+            "--background",
+            "black",
+            "--threads",
+            "3",
+            "--tile-size",
+            "16",
+            "--seed",
+            "42",
+            "--scene",
+            "scenes/three_spheres.toml",
+            "--output",
+            "out.ppm",
+            "--sample-map",
+            "samples.png",
+            "--bit-depth",
+            "8",
+            "--tonemap",
+            "aces",
+            "--exposure=-0.5",
+            "--white-point",
+            "6",
+            "--binary",
+            "--progressive",
+            "--quiet",
+        ]);
+        assert_eq!(
+            options,
+            Options {
+                width: Some(640),
+                aspect: Some(1.25),
+                spp: Some(8),
+                adaptive_threshold: Some(0.02),
+                min_spp: Some(4),
+                max_depth: Some(5),
+                roulette_depth: Some(0),
+                sampler: Some(SamplerKind::Stratified),
+                background: Some(Background::Solid { color: [0.0; 3] }),
+                threads: Some(3),
+                tile_size: Some(16),
+                seed: Some(42),
+                scene: Some(PathBuf::from("scenes/three_spheres.toml")),
+                output: Some(PathBuf::from("out.ppm")),
+                sample_map: Some(PathBuf::from("samples.png")),
+                bit_depth: Some(8),
+                tone_map: Some(ToneMapOperator::Aces),
+                exposure: Some(-0.5),
+                white_point: Some(6.0),
+                binary: true,
+                progressive: true,
+                quiet: true,
+            }
+        );
+        assert_eq!(
+            options.output_format(),
+            OutputFormat::Ppm(PpmEncoding::Binary)
+        );
+    }
+
+    #[test]
+    fn help_wins_over_other_options() {
+        assert_eq!(parse(&["--width", "10", "--help"]), Ok(Command::Help));
+        assert_eq!(parse(&["-h"]), Ok(Command::Help));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert_eq!(
+            parse_error(&["--width", "wide"]),
+            "invalid value 'wide' for --width"
+        );
+        assert_eq!(parse_error(&["--spp", "0"]), "--spp must be greater than 0");
+        assert_eq!(
+            parse_error(&["--aspect", "-1.5"]),
+            "--aspect must be greater than 0"
+        );
+        assert_eq!(
+            parse_error(&["--aspect", "inf"]),
+            "invalid value 'inf' for --aspect"
+        );
+        assert_eq!(
+            parse_error(&["--threads", "-2"]),
+            "invalid value '-2' for --threads"
+        );
+    }
+
+    #[test]
+    fn rejects_missing_values() {
+        assert_eq!(parse_error(&["--scene"]), "--scene needs a value");
+    }
+
+    #[test]
+    fn rejects_unknown_options() {
+        assert_eq!(parse_error(&["--colour"]), "unknown option '--colour'");
+        assert_eq!(parse_error(&["scene.toml"]), "unknown option 'scene.toml'");
+        assert_eq!(
+            parse_error(&["--quiet=yes"]),
+            "--quiet doesn't take a value"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_output_format() {
+        assert_eq!(
+            parse_error(&["--output", "img.gif"]),
+            "unsupported output format '.gif'"
+        );
+    }
+
+    #[test]
+    fn picks_bit_depth() {
+        let options = parse_options(&["--output", "out.png"]);
+        assert_eq!(options.output_format(), OutputFormat::Png(BitDepth::Eight));
+        let options = parse_options(&["--bit-depth=16", "--output", "out.png"]);
+        assert_eq!(
+            options.output_format(),
+            OutputFormat::Png(BitDepth::Sixteen)
+        );
+        let options = parse_options(&["--output", "out.exr"]);
+        assert_eq!(
+            options.output_format(),
+            OutputFormat::Exr(ExrPixelType::Half)
+        );
+        let options = parse_options(&["--output", "out.exr", "--bit-depth", "32"]);
+        assert_eq!(
+            options.output_format(),
+            OutputFormat::Exr(ExrPixelType::Float)
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_bit_depths() {
+        assert_eq!(
+            parse_error(&["--bit-depth", "deep"]),
+            "invalid value 'deep' for --bit-depth"
+        );
+        assert_eq!(
+            parse_error(&["--output", "out.png", "--bit-depth", "12"]),
+            "PNG output doesn't support a bit depth of 12"
+        );
+        assert_eq!(
+            parse_error(&["--bit-depth", "16"]),
+            "PPM output doesn't support a bit depth of 16"
+        );
+        assert_eq!(
+            parse_error(&["--output", "out.hdr", "--bit-depth", "32"]),
+            "Radiance HDR output doesn't support a bit depth of 32"
+        );
+    }
+
+    #[test]
+    fn defaults_to_plain_text_ppm() {
+        assert_eq!(
+            parse_options(&[]).output_format(),
+            OutputFormat::Ppm(PpmEncoding::Ascii)
+        );
+        assert_eq!(
+            parse_options(&["--output", "out.ppm"]).output_format(),
+            OutputFormat::Ppm(PpmEncoding::Ascii)
+        );
+        assert_eq!(
+            parse_options(&["--binary"]).output_format(),
+            OutputFormat::Ppm(PpmEncoding::Binary)
+        );
+        assert_eq!(
+            parse_error(&["--binary", "--output", "out.png"]),
+            "--binary only applies to PPM output"
+        );
+    }
+
+    #[test]
+    fn progressive_rendering_needs_an_output_file() {
+        assert_eq!(
+            parse_error(&["--progressive"]),
+            "--progressive needs --output"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_adaptive_sampling_options() {
+        assert_eq!(
+            parse_error(&["--adaptive-threshold", "0"]),
+            "--adaptive-threshold must be greater than 0"
+        );
+        assert_eq!(
+            parse_error(&["--sample-map", "samples.jpg"]),
+            "unsupported output format '.jpg'"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_tone_mapping() {
+        assert_eq!(
+            parse_error(&["--tonemap", "filmic"]),
+            "unknown tone mapping operator 'filmic' (known operators: clamp, reinhard, reinhard-extended, hable, aces)"
+        );
+        assert_eq!(
+            parse_error(&["--exposure", "nan"]),
+            "invalid value 'nan' for --exposure"
+        );
+        assert_eq!(
+            parse_error(&["--white-point", "0"]),
+            "--white-point must be greater than 0"
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::sampler::Sampler;
 use crate::utils::clamp;
 use crate::vec3::Vec3;
 use std::fmt;
@@ -17,14 +18,12 @@ impl Color {
         Color { vec }
     }
 
-    #[allow(dead_code)]
-    pub fn random() -> Self {
+    pub fn random(sampler: &mut dyn Sampler) -> Self {
         Color {
-            vec: Vec3(rand::random(), rand::random(), rand::random()),
+            vec: Vec3::random(sampler),
         }
     }
 
-    #[allow(dead_code)]
     pub fn red() -> Self {
         Self::new(1.0, 0.0, 0.0)
     }
@@ -54,30 +53,73 @@ impl Color {
     }
 }
 
+// The sRGB opto-electronic transfer function, from linear light in 0 to 1 to
+// the encoded value that displays expect.
+pub fn srgb_encode(linear: f64) -> f64 {
+    if linear <= 0.0031308 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// The inverse of srgb_encode, e.g. for image textures, which are stored
+// encoded but need to be in linear light to be lit.
+pub fn srgb_decode(encoded: f64) -> f64 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn quantize(color_component: f64, max: f64) -> f64 {
+    (srgb_encode(clamp(color_component, 0.0, 1.0)) * max).round()
+}
+
 pub struct RenderColor {
     r: u8,
     g: u8,
     b: u8,
 }
 
+// Colors are expected to already be tone mapped, so anything outside 0 to 1
+// is clamped.
 impl RenderColor {
     pub fn from_color(color: Color) -> Self {
-        fn gamma_correct(color_component: f64) -> f64 {
-            color_component.sqrt()
+        let scale_color = |color_component| quantize(color_component, u8::MAX as f64) as u8;
+        Self {
+            r: scale_color(color.r()),
+            g: scale_color(color.g()),
+            b: scale_color(color.b()),
         }
+    }
 
-        fn scale_color(color_component: f64) -> u32 {
-            // make sure we don't overshoot from to 255 to 256
-            let clamped = clamp(color_component, 0.0, 0.999);
-            (gamma_correct(clamped) * 256.0) as u32
-        }
+    pub fn rgb(&self) -> [u8; 3] {
+        [self.r, self.g, self.b]
+    }
+}
 
+// Like RenderColor, but with 16 bits per channel.
+pub struct DeepRenderColor {
+    r: u16,
+    g: u16,
+    b: u16,
+}
+
+impl DeepRenderColor {
+    pub fn from_color(color: Color) -> Self {
+        let scale_color = |color_component| quantize(color_component, u16::MAX as f64) as u16;
         Self {
-            r: scale_color(color.r()) as u8,
-            g: scale_color(color.g()) as u8,
-            b: scale_color(color.b()) as u8,
+            r: scale_color(color.r()),
+            g: scale_color(color.g()),
+            b: scale_color(color.b()),
         }
     }
+
+    pub fn rgb(&self) -> [u16; 3] {
+        [self.r, self.g, self.b]
+    }
 }
 
 impl fmt::Display for RenderColor {
@@ -85,3 +127,36 @@ impl fmt::Display for RenderColor {
         write!(f, "{} {} {}", self.r, self.g, self.b)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_srgb() {
+        assert_eq!(srgb_encode(0.0), 0.0);
+        assert!((srgb_encode(1.0) - 1.0).abs() < 1e-12);
+        // the linear and power segments meet
+        assert!((srgb_encode(0.0031308) - srgb_encode(0.0031309)).abs() < 1e-5);
+        assert!((srgb_encode(0.18) - 0.4614).abs() < 1e-4);
+    }
+
+    #[test]
+    fn decodes_srgb() {
+        for linear in [0.0, 0.001, 0.0031308, 0.18, 0.5, 1.0] {
+            assert!((srgb_decode(srgb_encode(linear)) - linear).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn quantizes_with_clamping() {
+        assert_eq!(
+            RenderColor::from_color(Color::new(2.0, -1.0, 0.5)).rgb(),
+            [255, 0, 188]
+        );
+        assert_eq!(
+            DeepRenderColor::from_color(Color::new(1.0, 0.0, 0.5)).rgb(),
+            [65535, 0, 48192]
+        );
+    }
+}
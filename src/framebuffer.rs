@@ -0,0 +1,191 @@
+use crate::color::Color;
+use crate::tile::Tile;
+
+// Linear colors for every pixel of an image, stored in rows from the top of
+// the image downwards, and how many samples each is the average of.
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+    sample_counts: Vec<u32>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::black(); width as usize * height as usize],
+            sample_counts: vec![0; width as usize * height as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "pixel ({}, {}) is outside a {}x{} framebuffer",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        y as usize * self.width as usize + x as usize
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Color {
+        self.pixels[self.index(x, y)]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, color: Color) {
+        let idx = self.index(x, y);
+        self.pixels[idx] = color;
+    }
+
+    pub fn set_row(&mut self, y: u32, colors: &[Color]) {
+        assert_eq!(colors.len(), self.width as usize);
+        let start = self.index(0, y);
+        self.pixels[start..start + colors.len()].copy_from_slice(colors);
+    }
+
+    // The colors are given row by row, from the top of the tile.
+    pub fn set_tile(&mut self, tile: &Tile, colors: &[Color]) {
+        assert_eq!(colors.len(), tile.width as usize * tile.height as usize);
+        for (tile_row, row_colors) in colors.chunks(tile.width as usize).enumerate() {
+            let start = self.index(tile.x, tile.y + tile_row as u32);
+            // the last pixel of the row must be inside the image too
+            self.index(tile.x + tile.width - 1, tile.y + tile_row as u32);
+            self.pixels[start..start + row_colors.len()].copy_from_slice(row_colors);
+        }
+    }
+
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = &[Color]> {
+        self.pixels.chunks(self.width as usize)
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    // The pixels are given row by row, from the top of the image.
+    pub fn from_pixels(width: u32, height: u32, pixels: &[Color]) -> Self {
+        assert_eq!(pixels.len(), width as usize * height as usize);
+        Self {
+            width,
+            height,
+            pixels: pixels.to_vec(),
+            sample_counts: vec![0; pixels.len()],
+        }
+    }
+
+    pub fn with_sample_counts(self, sample_counts: Vec<u32>) -> Self {
+        assert_eq!(sample_counts.len(), self.pixels.len());
+        Self {
+            sample_counts,
+            ..self
+        }
+    }
+
+    pub fn sample_counts(&self) -> &[u32] {
+        &self.sample_counts
+    }
+
+    // A grey image of how many samples each pixel got, from black for none
+    // to white for the most any pixel got.
+    pub fn sample_map(&self) -> Self {
+        let most = self.sample_counts.iter().copied().max().unwrap_or(0).max(1) as f64;
+        let pixels: Vec<Color> = self
+            .sample_counts
+            .iter()
+            .map(|count| {
+                let level = *count as f64 / most;
+                Color::new(level, level, level)
+            })
+            .collect();
+        Self::from_pixels(self.width, self.height, &pixels)
+            .with_sample_counts(self.sample_counts.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indexes_by_column_and_row() {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.set(2, 0, Color::white());
+        framebuffer.set_row(1, &[Color::new(0.1, 0.2, 0.3); 3]);
+        assert_eq!(framebuffer.get(2, 0).vec, Color::white().vec);
+        assert_eq!(framebuffer.get(0, 0).vec, Color::black().vec);
+        assert_eq!(framebuffer.get(1, 1).vec, Color::new(0.1, 0.2, 0.3).vec);
+        assert_eq!(framebuffer.pixels()[2].vec, Color::white().vec);
+        let rows: Vec<&[Color]> = framebuffer.rows().collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1][0].vec, Color::new(0.1, 0.2, 0.3).vec);
+    }
+
+    #[test]
+    fn sets_the_pixels_of_a_tile() {
+        let mut framebuffer = Framebuffer::new(4, 3);
+        let tile = Tile {
+            x: 1,
+            y: 1,
+            width: 2,
+            height: 2,
+        };
+        let colors: Vec<Color> = (0..4).map(|idx| Color::new(idx as f64, 0.0, 0.0)).collect();
+        framebuffer.set_tile(&tile, &colors);
+        let reds: Vec<f64> = framebuffer.pixels().iter().map(|color| color.r()).collect();
+        assert_eq!(
+            reds,
+            [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 2.0, 3.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn maps_sample_counts_to_greys() {
+        let framebuffer = Framebuffer::new(2, 2).with_sample_counts(vec![4, 16, 0, 8]);
+        let greys: Vec<f64> = framebuffer
+            .sample_map()
+            .pixels()
+            .iter()
+            .map(|color| color.g())
+            .collect();
+        assert_eq!(greys, [0.25, 1.0, 0.0, 0.5]);
+        // with nothing sampled it's all black
+        let greys: Vec<f64> = Framebuffer::new(2, 1)
+            .sample_map()
+            .pixels()
+            .iter()
+            .map(|color| color.g())
+            .collect();
+        assert_eq!(greys, [0.0, 0.0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_tiles_outside_the_image() {
+        let tile = Tile {
+            x: 2,
+            y: 0,
+            width: 2,
+            height: 1,
+        };
+        Framebuffer::new(3, 2).set_tile(&tile, &[Color::black(); 2]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_pixels_outside_the_image() {
+        Framebuffer::new(3, 2).get(3, 0);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::aabb::Aabb;
 use crate::material::Material;
 use crate::ray::Ray;
 use crate::vec3::Vec3;
@@ -10,6 +11,9 @@ pub struct Hit {
     pub ray_t: f64,
     pub ray: Ray,
     pub material: Arc<dyn Material + Send + Sync>,
+    // surface coordinates, for texture lookups
+    pub u: f64,
+    pub v: f64,
 }
 
 impl Hit {
@@ -36,6 +40,43 @@ impl Hit {
             ray_t,
             hit_point,
             material,
+            u: 0.0,
+            v: 0.0,
         }
     }
+
+    pub fn with_uv(self, u: f64, v: f64) -> Self {
+        Self { u, v, ..self }
+    }
+
+    // Replaces the normal used for shading (e.g. one interpolated between
+    // vertex normals) without changing which face we consider to have been
+    // hit - that is still decided by the geometric normal.
+    pub fn with_shading_normal(self, outwards_normal: Vec3) -> Self {
+        let normal = if self.front_face {
+            outwards_normal
+        } else {
+            -outwards_normal
+        };
+        Self { normal, ..self }
+    }
+}
+
+pub trait Hittable: Send + Sync {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<Hit>;
+    // None for objects which are unbounded
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    // For objects which can be sampled directly, e.g. lights: the density,
+    // per steradian, of random() giving a direction from origin. Objects which
+    // can't be sampled never give any direction.
+    fn pdf_value(&self, _origin: Vec3, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    // A random direction from origin towards the object, made from a point
+    // evenly distributed over the unit square.
+    fn random(&self, _origin: Vec3, _sample: (f64, f64)) -> Vec3 {
+        Vec3(1.0, 0.0, 0.0)
+    }
 }
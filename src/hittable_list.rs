@@ -1,19 +1,30 @@
-use crate::hittable::Hit;
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable};
 use crate::ray::Ray;
-use crate::sphere::ObjectSphere;
+use crate::vec3::Vec3;
+use std::sync::Arc;
 
-#[derive(Clone)]
-pub struct HittableList(Vec<ObjectSphere>);
+#[derive(Clone, Default)]
+pub struct HittableList(Vec<Arc<dyn Hittable>>);
 
 impl HittableList {
-    #[allow(dead_code)]
-    fn clear(&mut self) {
-        self.0.clear();
+    pub fn add<T: Hittable + 'static>(&mut self, obj: T) {
+        self.0.push(Arc::new(obj));
     }
-    pub fn add(&mut self, obj: ObjectSphere) {
+    // Adds an object which may also be used elsewhere, e.g. by instances.
+    pub fn add_shared(&mut self, obj: Arc<dyn Hittable>) {
         self.0.push(obj);
     }
-    pub fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+    pub fn objects(&self) -> &[Arc<dyn Hittable>] {
+        &self.0
+    }
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl Hittable for HittableList {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<Hit> {
         let mut closest_hit = None;
         for hittable in self.0.iter() {
             let closest_so_far = if let Some(Hit { ray_t, .. }) = closest_hit {
@@ -27,7 +38,98 @@ impl HittableList {
         }
         closest_hit
     }
-    pub fn new() -> Self {
-        Self(Vec::new())
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut boxes = self.0.iter().map(|hittable| hittable.bounding_box());
+        let first_box = boxes.next()??;
+        boxes.try_fold(first_box, |surrounding_box, bounding_box| {
+            Some(Aabb::surrounding(surrounding_box, bounding_box?))
+        })
+    }
+
+    // Samples each object equally often, so the density is the average of
+    // theirs.
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        if self.0.is_empty() {
+            return 0.0;
+        }
+        let total: f64 = self
+            .0
+            .iter()
+            .map(|hittable| hittable.pdf_value(origin, direction))
+            .sum();
+        total / self.0.len() as f64
+    }
+
+    // The first part of the sample picks the object, and is then stretched
+    // back over 0 to 1 for it to use.
+    fn random(&self, origin: Vec3, (u, v): (f64, f64)) -> Vec3 {
+        if self.0.is_empty() {
+            return Vec3(1.0, 0.0, 0.0);
+        }
+        let scaled = u * self.0.len() as f64;
+        let idx = (scaled as usize).min(self.0.len() - 1);
+        self.0[idx].random(origin, (scaled - idx as f64, v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::ObjectSphere;
+    use rand::random;
+
+    fn sphere_at(center: Vec3) -> ObjectSphere {
+        ObjectSphere::new(1.0, center, Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))))
+    }
+
+    #[test]
+    fn nested_list_finds_closest_hit() {
+        let mut inner = HittableList::new();
+        inner.add(sphere_at(Vec3(0.0, 0.0, -5.0)));
+        let mut outer = HittableList::new();
+        outer.add(sphere_at(Vec3(0.0, 0.0, -10.0)));
+        outer.add(inner);
+        let ray = Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, -1.0));
+        assert_eq!(outer.hit(ray, 0.0, f64::INFINITY).unwrap().ray_t, 4.0);
+    }
+
+    #[test]
+    fn bounding_box_surrounds_all_objects() {
+        let mut list = HittableList::new();
+        list.add(sphere_at(Vec3(0.0, 0.0, 0.0)));
+        list.add(sphere_at(Vec3(5.0, 2.0, -3.0)));
+        let bounding_box = list.bounding_box().unwrap();
+        assert_eq!(bounding_box.min, Vec3(-1.0, -1.0, -4.0));
+        assert_eq!(bounding_box.max, Vec3(6.0, 3.0, 1.0));
+    }
+
+    #[test]
+    fn samples_each_object_equally() {
+        let mut list = HittableList::new();
+        list.add(sphere_at(Vec3(0.0, 0.0, -5.0)));
+        list.add(sphere_at(Vec3(0.0, 0.0, 5.0)));
+        let origin = Vec3(0.0, 0.0, 0.0);
+        let towards = |z: f64| list.pdf_value(origin, Vec3(0.0, 0.0, z));
+        assert!(towards(-1.0) > 0.0);
+        assert_eq!(towards(-1.0), towards(1.0));
+        assert_eq!(
+            towards(-1.0) * 2.0,
+            list.objects()[0].pdf_value(origin, Vec3(0.0, 0.0, -1.0))
+        );
+        let behind = (0..10_000)
+            .filter(|_| list.random(origin, (random(), random())).2 > 0.0)
+            .count();
+        assert!((behind as f64 / 10_000.0 - 0.5).abs() < 0.03);
+        assert_eq!(
+            HittableList::new().pdf_value(origin, Vec3(0.0, 0.0, 1.0)),
+            0.0
+        );
+    }
+
+    #[test]
+    fn empty_list_has_no_bounding_box() {
+        assert!(HittableList::new().bounding_box().is_none());
     }
 }
@@ -0,0 +1,184 @@
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable};
+use crate::mat4::Mat4;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use std::sync::Arc;
+
+// An object placed in the world by an affine transform. The object itself can
+// be shared between many instances.
+pub struct Instance {
+    object: Arc<dyn Hittable>,
+    object_to_world: Mat4,
+    world_to_object: Mat4,
+    // normals transform by the inverse transpose, so that they stay
+    // perpendicular to surfaces that have been scaled unevenly
+    normal_to_world: Mat4,
+    bounding_box: Option<Aabb>,
+}
+
+impl Instance {
+    // None if the transform can't be inverted, e.g. it scales by zero.
+    pub fn new(object: Arc<dyn Hittable>, object_to_world: Mat4) -> Option<Self> {
+        let world_to_object = object_to_world.inverse()?;
+        // the transformed corners of the object's box are enclosed by the new
+        // box, even when the transform rotates
+        let bounding_box = object.bounding_box().map(|object_box| {
+            let corner = |corner_idx: usize| {
+                let pick = |bit: usize, axis: usize| {
+                    if corner_idx & bit == 0 {
+                        object_box.min[axis]
+                    } else {
+                        object_box.max[axis]
+                    }
+                };
+                object_to_world.transform_point(Vec3(pick(1, 0), pick(2, 1), pick(4, 2)))
+            };
+            let first = corner(0);
+            let mut bounding_box = Aabb::new(first, first);
+            for corner_idx in 1..8 {
+                let point = corner(corner_idx);
+                bounding_box = Aabb::surrounding(bounding_box, Aabb::new(point, point));
+            }
+            bounding_box
+        });
+        Some(Self {
+            object,
+            object_to_world,
+            world_to_object,
+            normal_to_world: world_to_object.transpose(),
+            bounding_box,
+        })
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        // The object space ray isn't normalised, so that t means the same
+        // thing in both spaces.
+        let object_ray = Ray::new(
+            self.world_to_object.transform_point(ray.origin),
+            self.world_to_object.transform_vector(ray.vector),
+        )
+        .with_time(ray.time);
+        let hit = self.object.hit(object_ray, t_min, t_max)?;
+        // The normal already faces against the object space ray, and the
+        // inverse transpose keeps it facing against the world space one.
+        Some(Hit {
+            normal: self
+                .normal_to_world
+                .transform_vector(hit.normal)
+                .unit_vector(),
+            hit_point: self.object_to_world.transform_point(hit.hit_point),
+            ray,
+            ..hit
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bounding_box
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::plane::Plane;
+    use crate::rect::Cuboid;
+    use crate::sphere::ObjectSphere;
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{} != {}", a, b);
+    }
+
+    fn unit_cube() -> Arc<dyn Hittable> {
+        Arc::new(Cuboid::new(
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(1.0, 1.0, 1.0),
+            Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+        ))
+    }
+
+    #[test]
+    fn translated_sphere_is_hit_where_it_was_moved_to() {
+        let sphere: Arc<dyn Hittable> = Arc::new(ObjectSphere::new(
+            1.0,
+            Vec3(0.0, 0.0, 0.0),
+            Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+        ));
+        let instance = Instance::new(sphere, Mat4::translation(Vec3(0.0, 0.0, -10.0))).unwrap();
+        let ray = Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, -1.0));
+        let hit = instance.hit(ray, 0.0, f64::INFINITY).unwrap();
+        assert_eq!(hit.ray_t, 9.0);
+        assert_eq!(hit.hit_point, Vec3(0.0, 0.0, -9.0));
+        assert_eq!(hit.normal, Vec3(0.0, 0.0, 1.0));
+        assert_eq!(hit.ray.origin, ray.origin);
+        let bounding_box = instance.bounding_box().unwrap();
+        assert_eq!(bounding_box.min, Vec3(-1.0, -1.0, -11.0));
+        assert_eq!(bounding_box.max, Vec3(1.0, 1.0, -9.0));
+    }
+
+    #[test]
+    fn rotated_box_gets_a_box_around_its_corners() {
+        let instance =
+            Instance::new(unit_cube(), Mat4::rotation(Vec3(0.0, 1.0, 0.0), 45.0)).unwrap();
+        let bounding_box = instance.bounding_box().unwrap();
+        let half_diagonal = 0.5f64.sqrt();
+        assert_near(bounding_box.min, Vec3(0.0, 0.0, -half_diagonal));
+        assert_near(
+            bounding_box.max,
+            Vec3(2.0 * half_diagonal, 1.0, half_diagonal),
+        );
+
+        // the corner that was at (1, 0, 1) now points straight along x, so
+        // just beside it is the face that was at z = 1
+        let ray = Ray::new(Vec3(5.0, 0.5, 0.1), Vec3(-1.0, 0.0, 0.0));
+        let hit = instance.hit(ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.ray_t - (5.0 - (2.0 * half_diagonal - 0.1))).abs() < 1e-9);
+        // turned 45 degrees
+        assert_near(hit.normal, Vec3(half_diagonal, 0.0, half_diagonal));
+        assert!(hit.front_face);
+    }
+
+    #[test]
+    fn normals_use_inverse_transpose_under_uneven_scaling() {
+        // a sphere squashed into an ellipsoid twice as wide as it is tall
+        let sphere: Arc<dyn Hittable> = Arc::new(ObjectSphere::new(
+            1.0,
+            Vec3(0.0, 0.0, 0.0),
+            Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+        ));
+        let instance = Instance::new(sphere, Mat4::scaling(Vec3(2.0, 1.0, 1.0))).unwrap();
+        // x^2/4 + y^2 = 1 at x = sqrt(2), y = sqrt(1/2)
+        let point = Vec3(2f64.sqrt(), 0.5f64.sqrt(), 0.0);
+        let ray = Ray::new(point * 2.0, -point);
+        let hit = instance.hit(ray, 0.0, f64::INFINITY).unwrap();
+        assert_near(hit.hit_point, point);
+        // the gradient of x^2/4 + y^2 there is (x/2, 2y, 0)
+        assert_near(
+            hit.normal,
+            Vec3(point.0 / 2.0, 2.0 * point.1, 0.0).unit_vector(),
+        );
+    }
+
+    #[test]
+    fn singular_transform_is_rejected() {
+        assert!(Instance::new(unit_cube(), Mat4::scaling(Vec3(0.0, 1.0, 1.0))).is_none());
+    }
+
+    #[test]
+    fn unbounded_objects_stay_unbounded() {
+        let plane: Arc<dyn Hittable> = Arc::new(Plane::new(
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(0.0, 1.0, 0.0),
+            Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+        ));
+        let instance = Instance::new(plane, Mat4::rotation(Vec3(1.0, 0.0, 0.0), 90.0)).unwrap();
+        assert!(instance.bounding_box().is_none());
+        // the plane now faces along z
+        let ray = Ray::new(Vec3(3.0, 4.0, 5.0), Vec3(0.0, 0.0, -1.0));
+        let hit = instance.hit(ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.ray_t - 5.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,44 @@
+// A path tracer, after Ray Tracing in One Weekend and its sequels. Load or
+// build a Scene, then render it with a Renderer:
+//
+//     let scene = Scene::load(Path::new("scenes/cornell_box.toml"))?;
+//     let framebuffer = Renderer::new(&scene).render();
+//
+// and write the framebuffer out with output::write_image.
+
+pub mod aabb;
+pub mod background;
+pub mod bvh;
+pub mod camera;
+pub mod color;
+pub mod framebuffer;
+pub mod hittable;
+pub mod hittable_list;
+pub mod instance;
+pub mod mat4;
+pub mod material;
+pub mod medium;
+pub mod obj;
+pub mod output;
+pub mod pdf;
+pub mod perlin;
+mod pixel;
+pub mod plane;
+pub mod ray;
+pub mod rect;
+pub mod render;
+pub mod sampler;
+pub mod scene;
+pub mod sphere;
+pub mod texture;
+pub mod tile;
+pub mod tonemap;
+pub mod triangle;
+mod utils;
+mod validate;
+pub mod vec3;
+
+pub use camera::{Camera, CameraSettings};
+pub use framebuffer::Framebuffer;
+pub use render::{Renderer, TileProgress};
+pub use scene::{RenderSettings, Scene};
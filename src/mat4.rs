@@ -0,0 +1,239 @@
+use crate::vec3::Vec3;
+use std::ops;
+
+// Row-major 4x4 matrix for affine transforms of points and vectors, which are
+// treated as column vectors with an implicit w of 1 and 0 respectively.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4(pub [[f64; 4]; 4]);
+
+impl Mat4 {
+    pub fn identity() -> Self {
+        let mut rows = [[0.0; 4]; 4];
+        for (idx, row) in rows.iter_mut().enumerate() {
+            row[idx] = 1.0;
+        }
+        Self(rows)
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        let mut matrix = Self::identity();
+        matrix.0[0][3] = offset.0;
+        matrix.0[1][3] = offset.1;
+        matrix.0[2][3] = offset.2;
+        matrix
+    }
+
+    pub fn scaling(factors: Vec3) -> Self {
+        let mut matrix = Self::identity();
+        matrix.0[0][0] = factors.0;
+        matrix.0[1][1] = factors.1;
+        matrix.0[2][2] = factors.2;
+        matrix
+    }
+
+    // Rotation counter-clockwise about an axis through the origin, when
+    // looking back along the axis towards the origin.
+    pub fn rotation(axis: Vec3, degrees: f64) -> Self {
+        let Vec3(x, y, z) = axis.unit_vector();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let t = 1.0 - cos;
+        // Rodrigues' rotation formula
+        Self([
+            [
+                t * x * x + cos,
+                t * x * y - sin * z,
+                t * x * z + sin * y,
+                0.0,
+            ],
+            [
+                t * x * y + sin * z,
+                t * y * y + cos,
+                t * y * z - sin * x,
+                0.0,
+            ],
+            [
+                t * x * z - sin * y,
+                t * y * z + sin * x,
+                t * z * z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut rows = [[0.0; 4]; 4];
+        for (row_idx, row) in rows.iter_mut().enumerate() {
+            for (col_idx, value) in row.iter_mut().enumerate() {
+                *value = self.0[col_idx][row_idx];
+            }
+        }
+        Self(rows)
+    }
+
+    // Gauss-Jordan elimination with partial pivoting. None if the matrix is
+    // singular, e.g. a scale by zero, or has any infinite or NaN entries.
+    pub fn inverse(&self) -> Option<Self> {
+        if self.0.iter().flatten().any(|value| !value.is_finite()) {
+            return None;
+        }
+        let mut matrix = self.0;
+        let mut inverse = Self::identity().0;
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|a, b| matrix[*a][col].abs().total_cmp(&matrix[*b][col].abs()))
+                .unwrap();
+            if matrix[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            matrix.swap(col, pivot);
+            inverse.swap(col, pivot);
+            let scale = 1.0 / matrix[col][col];
+            for idx in 0..4 {
+                matrix[col][idx] *= scale;
+                inverse[col][idx] *= scale;
+            }
+            for row in 0..4 {
+                if row == col {
+                    continue;
+                }
+                let factor = matrix[row][col];
+                for idx in 0..4 {
+                    matrix[row][idx] -= factor * matrix[col][idx];
+                    inverse[row][idx] -= factor * inverse[col][idx];
+                }
+            }
+        }
+        Some(Self(inverse))
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        let m = &self.0;
+        Vec3(
+            m[0][0] * point.0 + m[0][1] * point.1 + m[0][2] * point.2 + m[0][3],
+            m[1][0] * point.0 + m[1][1] * point.1 + m[1][2] * point.2 + m[1][3],
+            m[2][0] * point.0 + m[2][1] * point.1 + m[2][2] * point.2 + m[2][3],
+        )
+    }
+
+    // Ignores the translation part, as for directions.
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        let m = &self.0;
+        Vec3(
+            m[0][0] * vector.0 + m[0][1] * vector.1 + m[0][2] * vector.2,
+            m[1][0] * vector.0 + m[1][1] * vector.1 + m[1][2] * vector.2,
+            m[2][0] * vector.0 + m[2][1] * vector.1 + m[2][2] * vector.2,
+        )
+    }
+}
+
+// Composition: (a * b) applies b first, then a.
+impl ops::Mul for Mat4 {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        let mut rows = [[0.0; 4]; 4];
+        for (row_idx, row) in rows.iter_mut().enumerate() {
+            for (col_idx, value) in row.iter_mut().enumerate() {
+                *value = (0..4)
+                    .map(|idx| self.0[row_idx][idx] * rhs.0[idx][col_idx])
+                    .sum();
+            }
+        }
+        Self(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{} != {}", a, b);
+    }
+
+    fn assert_matrix_near(a: Mat4, b: Mat4) {
+        for row in 0..4 {
+            for col in 0..4 {
+                assert!(
+                    (a.0[row][col] - b.0[row][col]).abs() < 1e-9,
+                    "{:?} != {:?}",
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn translates_points_but_not_vectors() {
+        let translation = Mat4::translation(Vec3(1.0, 2.0, 3.0));
+        assert_eq!(
+            translation.transform_point(Vec3(1.0, 1.0, 1.0)),
+            Vec3(2.0, 3.0, 4.0)
+        );
+        assert_eq!(
+            translation.transform_vector(Vec3(1.0, 1.0, 1.0)),
+            Vec3(1.0, 1.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn rotates_counter_clockwise() {
+        let about_y = Mat4::rotation(Vec3(0.0, 1.0, 0.0), 90.0);
+        assert_near(
+            about_y.transform_point(Vec3(1.0, 0.0, 0.0)),
+            Vec3(0.0, 0.0, -1.0),
+        );
+        let about_z = Mat4::rotation(Vec3(0.0, 0.0, 2.0), 90.0);
+        assert_near(
+            about_z.transform_point(Vec3(1.0, 0.0, 0.0)),
+            Vec3(0.0, 1.0, 0.0),
+        );
+        let about_diagonal = Mat4::rotation(Vec3(1.0, 1.0, 1.0), 120.0);
+        assert_near(
+            about_diagonal.transform_point(Vec3(1.0, 0.0, 0.0)),
+            Vec3(0.0, 1.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn composes_right_to_left() {
+        let scale_then_translate =
+            Mat4::translation(Vec3(1.0, 0.0, 0.0)) * Mat4::scaling(Vec3(2.0, 2.0, 2.0));
+        assert_eq!(
+            scale_then_translate.transform_point(Vec3(1.0, 1.0, 1.0)),
+            Vec3(3.0, 2.0, 2.0)
+        );
+    }
+
+    #[test]
+    fn inverts_affine_matrices() {
+        let matrix = Mat4::translation(Vec3(1.0, -2.0, 3.0))
+            * Mat4::rotation(Vec3(0.3, 1.0, -0.2), 37.0)
+            * Mat4::scaling(Vec3(2.0, 0.5, 3.0));
+        let inverse = matrix.inverse().unwrap();
+        assert_matrix_near(matrix * inverse, Mat4::identity());
+        assert_matrix_near(inverse * matrix, Mat4::identity());
+        let point = Vec3(0.1, 0.2, 0.3);
+        assert_near(
+            inverse.transform_point(matrix.transform_point(point)),
+            point,
+        );
+    }
+
+    #[test]
+    fn singular_matrices_have_no_inverse() {
+        assert!(Mat4::scaling(Vec3(1.0, 0.0, 1.0)).inverse().is_none());
+        assert!(Mat4::scaling(Vec3(1.0, f64::NAN, 1.0)).inverse().is_none());
+        assert!(Mat4::translation(Vec3(f64::INFINITY, 0.0, 0.0))
+            .inverse()
+            .is_none());
+    }
+
+    #[test]
+    fn transposes() {
+        let matrix = Mat4::translation(Vec3(1.0, 2.0, 3.0));
+        assert_eq!(matrix.transpose().0[3], [1.0, 2.0, 3.0, 1.0]);
+        assert_eq!(matrix.transpose().transpose(), matrix);
+    }
+}
@@ -1,114 +1,145 @@
 use crate::color::Color;
 use crate::hittable::Hit;
+use crate::pdf::{CosinePdf, Pdf, SpherePdf};
 use crate::ray::Ray;
-use crate::sphere::GeometricSphere;
+use crate::sampler::Sampler;
+use crate::texture::{SolidColor, Texture};
 use crate::vec3::Vec3;
-use rand::random;
+use std::sync::Arc;
 
 pub struct ScatterResult {
     pub material_color: Color,
     pub scattered_ray: Ray,
+    // The density the material scatters with, which scattered_ray was drawn
+    // from. The renderer may pick a different direction, e.g. towards a
+    // light, and weight it by this. None for materials whose scattered ray
+    // has to be followed as it is, like mirrors.
+    pub pdf: Option<Box<dyn Pdf>>,
 }
 
 pub trait Material {
-    fn scatter(&self, hit: &Hit) -> Option<ScatterResult>;
+    // Any random choices are made with numbers from the sampler.
+    fn scatter(&self, hit: &Hit, sampler: &mut dyn Sampler) -> Option<ScatterResult>;
+    // light given off by the surface itself
+    fn emitted(&self, _hit: &Hit) -> Color {
+        Color::black()
+    }
 }
 
 pub struct Lambertian {
-    pub color: Color,
+    pub texture: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(color_vec: Vec3) -> Self {
-        Self {
-            color: Color::from_vec(color_vec),
-        }
+        Self::textured(Arc::new(SolidColor::new(color_vec)))
     }
-}
 
-pub struct RandomInSphere {
-    pub color: Color,
-}
-
-pub struct Hemispherical {
-    pub color: Color,
+    pub fn textured(texture: Arc<dyn Texture>) -> Self {
+        Self { texture }
+    }
 }
 
 pub struct Metal {
-    pub color: Color,
+    pub texture: Arc<dyn Texture>,
     pub fuzz: f64,
 }
 
 impl Metal {
     pub fn new(color_vec: Vec3, fuzz: f64) -> Self {
-        Self {
-            color: Color::from_vec(color_vec),
-            fuzz,
-        }
+        Self::textured(Arc::new(SolidColor::new(color_vec)), fuzz)
+    }
+
+    pub fn textured(texture: Arc<dyn Texture>, fuzz: f64) -> Self {
+        Self { texture, fuzz }
     }
 }
 
 pub struct Dielectric {
-    pub color: Color,
+    pub texture: Arc<dyn Texture>,
     pub refractive_index: f64,
 }
 
 impl Dielectric {
     pub fn new(color_vec: Vec3, refractive_index: f64) -> Self {
+        Self::textured(Arc::new(SolidColor::new(color_vec)), refractive_index)
+    }
+
+    pub fn textured(texture: Arc<dyn Texture>, refractive_index: f64) -> Self {
         Self {
-            color: Color::from_vec(color_vec),
+            texture,
             refractive_index,
         }
     }
 }
 
-impl Material for Lambertian {
-    fn scatter(&self, hit: &Hit) -> Option<ScatterResult> {
-        let reflection_vector = Vec3::random_from_range(&(-1.0..1.0));
-        Some(ScatterResult {
-            material_color: self.color,
-            scattered_ray: Ray::new(hit.hit_point, hit.normal + reflection_vector),
-        })
+pub struct DiffuseLight {
+    pub texture: Arc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(color_vec: Vec3) -> Self {
+        Self::textured(Arc::new(SolidColor::new(color_vec)))
+    }
+
+    pub fn textured(texture: Arc<dyn Texture>) -> Self {
+        Self { texture }
     }
 }
 
-impl Material for RandomInSphere {
-    fn scatter(&self, hit: &Hit) -> Option<ScatterResult> {
-        let reflection_vector = GeometricSphere::unit().random_point_in();
-        let mut scatter_direction = hit.normal + reflection_vector;
-        // catch degenerate scatter direction
-        if scatter_direction.is_near_zero() {
-            scatter_direction = hit.normal;
-        }
-        Some(ScatterResult {
-            material_color: self.color,
-            scattered_ray: Ray::new(hit.hit_point, scatter_direction),
-        })
+// Scatters in every direction equally, as particles in a volume do.
+pub struct Isotropic {
+    pub texture: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(color_vec: Vec3) -> Self {
+        Self::textured(Arc::new(SolidColor::new(color_vec)))
+    }
+
+    pub fn textured(texture: Arc<dyn Texture>) -> Self {
+        Self { texture }
     }
 }
 
-impl Material for Hemispherical {
-    fn scatter(&self, hit: &Hit) -> Option<ScatterResult> {
-        let reflection_vector = GeometricSphere::unit().random_point_in_hemisphere(hit.normal);
+impl Material for Lambertian {
+    fn scatter(&self, hit: &Hit, sampler: &mut dyn Sampler) -> Option<ScatterResult> {
+        let pdf = CosinePdf::new(hit.normal);
+        let direction = pdf.generate(sampler.get_2d());
         Some(ScatterResult {
-            material_color: self.color,
-            scattered_ray: Ray::new(hit.hit_point, hit.normal + reflection_vector),
+            material_color: self.texture.value(hit.u, hit.v, hit.hit_point),
+            scattered_ray: Ray::new(hit.hit_point, direction).with_time(hit.ray.time),
+            pdf: Some(Box::new(pdf)),
         })
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, hit: &Hit) -> Option<ScatterResult> {
+    fn scatter(&self, hit: &Hit, sampler: &mut dyn Sampler) -> Option<ScatterResult> {
         let reflected_ray_vector = hit.ray.vector.unit_vector().reflect(hit.normal);
-        let fuzz = GeometricSphere::unit().random_point_in() * self.fuzz;
+        // a point evenly distributed within a sphere of radius fuzz
+        let fuzz_direction = SpherePdf.generate(sampler.get_2d());
+        let fuzz = fuzz_direction * sampler.get_1d().cbrt() * self.fuzz;
         let vector = reflected_ray_vector + fuzz;
         // fuzz may have taken the vector below the surface
         if vector.dot(hit.normal) < 0.0 {
             return None;
         }
         Some(ScatterResult {
-            material_color: self.color,
-            scattered_ray: Ray::new(hit.hit_point, vector),
+            material_color: self.texture.value(hit.u, hit.v, hit.hit_point),
+            scattered_ray: Ray::new(hit.hit_point, vector).with_time(hit.ray.time),
+            pdf: None,
+        })
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, hit: &Hit, sampler: &mut dyn Sampler) -> Option<ScatterResult> {
+        let direction = SpherePdf.generate(sampler.get_2d());
+        Some(ScatterResult {
+            material_color: self.texture.value(hit.u, hit.v, hit.hit_point),
+            scattered_ray: Ray::new(hit.hit_point, direction).with_time(hit.ray.time),
+            pdf: Some(Box::new(SpherePdf)),
         })
     }
 }
@@ -128,7 +159,7 @@ fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, hit: &Hit) -> Option<ScatterResult> {
+    fn scatter(&self, hit: &Hit, sampler: &mut dyn Sampler) -> Option<ScatterResult> {
         let refractive_index_ratio = if hit.front_face {
             1.0 / self.refractive_index
         } else {
@@ -138,15 +169,65 @@ impl Material for Dielectric {
         let cos_theta = f64::min((-unit_direction).dot(hit.normal), 1.0);
         let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
         let cannot_refract = refractive_index_ratio * sin_theta > 1.0;
-        let direction =
-            if cannot_refract || reflectance(cos_theta, refractive_index_ratio) > random() {
-                unit_direction.reflect(hit.normal)
-            } else {
-                refract(unit_direction, hit.normal, refractive_index_ratio)
-            };
+        let direction = if cannot_refract
+            || reflectance(cos_theta, refractive_index_ratio) > sampler.get_1d()
+        {
+            unit_direction.reflect(hit.normal)
+        } else {
+            refract(unit_direction, hit.normal, refractive_index_ratio)
+        };
         Some(ScatterResult {
-            material_color: self.color,
-            scattered_ray: Ray::new(hit.hit_point, direction),
+            material_color: self.texture.value(hit.u, hit.v, hit.hit_point),
+            scattered_ray: Ray::new(hit.hit_point, direction).with_time(hit.ray.time),
+            pdf: None,
         })
     }
 }
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _hit: &Hit, _sampler: &mut dyn Sampler) -> Option<ScatterResult> {
+        None
+    }
+
+    // Only the front face glows, so e.g. a ceiling light can be flipped to
+    // shine down without also lighting the space above it.
+    fn emitted(&self, hit: &Hit) -> Color {
+        if !hit.front_face {
+            return Color::black();
+        }
+        self.texture.value(hit.u, hit.v, hit.hit_point)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a hit on a surface at the origin whose outward normal is +z
+    fn hit_from(origin: Vec3, material: Arc<dyn Material + Send + Sync>) -> Hit {
+        let ray = Ray::new(origin, -origin);
+        Hit::new(Vec3(0.0, 0.0, 1.0), Vec3(0.0, 0.0, 0.0), ray, 1.0, material)
+    }
+
+    #[test]
+    fn diffuse_light_emits_from_its_front_face_only() {
+        let light: Arc<dyn Material + Send + Sync> =
+            Arc::new(DiffuseLight::new(Vec3(4.0, 2.0, 1.0)));
+        let front = hit_from(Vec3(0.0, 0.0, 1.0), Arc::clone(&light));
+        assert!(front.front_face);
+        assert_eq!(light.emitted(&front).vec, Vec3(4.0, 2.0, 1.0));
+        let back = hit_from(Vec3(0.0, 0.0, -1.0), Arc::clone(&light));
+        assert!(!back.front_face);
+        assert_eq!(light.emitted(&back).vec, Vec3(0.0, 0.0, 0.0));
+        // and it never scatters
+        let mut sampler = crate::sampler::IndependentSampler::new(0);
+        assert!(light.scatter(&front, &mut sampler).is_none());
+    }
+
+    #[test]
+    fn other_materials_emit_nothing() {
+        let matte: Arc<dyn Material + Send + Sync> = Arc::new(Lambertian::new(Vec3(1.0, 1.0, 1.0)));
+        let hit = hit_from(Vec3(0.0, 0.0, 1.0), Arc::clone(&matte));
+        assert_eq!(matte.emitted(&hit).vec, Vec3(0.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,205 @@
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::{hash, hash_to_level};
+use crate::vec3::Vec3;
+use std::sync::Arc;
+
+// A volume of uniform density, like fog or smoke, filling a closed boundary
+// shape. Rays passing through may scatter at any point inside, with the
+// chance of getting a given distance without scattering falling off
+// exponentially with the density.
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    neg_inv_density: f64,
+    // how particles in the volume scatter light, e.g. Isotropic
+    phase_function: Arc<dyn Material + Send + Sync>,
+}
+
+impl ConstantMedium {
+    pub fn new(
+        boundary: Arc<dyn Hittable>,
+        density: f64,
+        phase_function: Arc<dyn Material + Send + Sync>,
+    ) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        // Find where the ray enters and leaves the boundary, looking along
+        // the whole line so that rays starting inside are handled too. This
+        // assumes the boundary is convex.
+        let entry = self.boundary.hit(ray, f64::NEG_INFINITY, f64::INFINITY)?;
+        let exit = self
+            .boundary
+            .hit(ray, entry.ray_t + 0.0001, f64::INFINITY)?;
+        let entry_t = entry.ray_t.max(t_min).max(0.0);
+        let exit_t = exit.ray_t.min(t_max);
+        if entry_t >= exit_t {
+            return None;
+        }
+        let ray_length = ray.vector.length();
+        let distance_inside = (exit_t - entry_t) * ray_length;
+        // Hittables aren't given a sampler, so take the random number from
+        // the ray itself, which is as good as random since no two rays are
+        // quite the same. That keeps renders reproducible.
+        let level = hash_to_level(hash(&[
+            ray.origin.0.to_bits(),
+            ray.origin.1.to_bits(),
+            ray.origin.2.to_bits(),
+            ray.vector.0.to_bits(),
+            ray.vector.1.to_bits(),
+            ray.vector.2.to_bits(),
+            ray.time.to_bits(),
+        ]));
+        let hit_distance = self.neg_inv_density * (1.0 - level).ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+        let ray_t = entry_t + hit_distance / ray_length;
+        // there's no surface, so the normal is arbitrary
+        Some(Hit {
+            normal: Vec3(1.0, 0.0, 0.0),
+            front_face: true,
+            hit_point: ray.at(ray_t),
+            ray_t,
+            ray,
+            material: Arc::clone(&self.phase_function),
+            u: 0.0,
+            v: 0.0,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Isotropic;
+    use crate::rect::Cuboid;
+    use crate::sampler::IndependentSampler;
+    use crate::sphere::ObjectSphere;
+
+    fn unit_sphere_medium(density: f64) -> ConstantMedium {
+        let material = Arc::new(Isotropic::new(Vec3(0.5, 0.5, 0.5)));
+        ConstantMedium::new(
+            Arc::new(ObjectSphere::new(
+                1.0,
+                Vec3(0.0, 0.0, 0.0),
+                material.clone(),
+            )),
+            density,
+            material,
+        )
+    }
+
+    #[test]
+    fn dense_medium_scatters_at_its_surface() {
+        let medium = unit_sphere_medium(1e9);
+        let ray = Ray::new(Vec3(0.0, 0.0, 5.0), Vec3(0.0, 0.0, -2.0));
+        let hit = medium.hit(ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.ray_t - 2.0).abs() < 1e-6);
+        assert!((hit.hit_point - Vec3(0.0, 0.0, 1.0)).length() < 1e-6);
+    }
+
+    #[test]
+    fn thin_medium_lets_rays_through() {
+        let medium = unit_sphere_medium(1e-9);
+        assert!((0..100).all(|idx| {
+            let ray = Ray::new(Vec3(idx as f64 * 1e-3, 0.0, 5.0), Vec3(0.0, 0.0, -1.0));
+            medium.hit(ray, 0.0, f64::INFINITY).is_none()
+        }));
+        // and rays which miss the boundary never scatter
+        let medium = unit_sphere_medium(1e9);
+        let miss = Ray::new(Vec3(0.0, 5.0, 5.0), Vec3(0.0, 0.0, -1.0));
+        assert!(medium.hit(miss, 0.0, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn scatters_inside_the_boundary_only() {
+        let medium = unit_sphere_medium(0.5);
+        for idx in 0..1000 {
+            let ray = Ray::new(Vec3(idx as f64 * 1e-4, 0.3, 5.0), Vec3(0.0, 0.0, -1.0));
+            if let Some(hit) = medium.hit(ray, 0.0, f64::INFINITY) {
+                assert!(hit.hit_point.length() <= 1.0 + 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn chance_of_scattering_follows_beer_lambert() {
+        // a slab 2 thick, so the chance of getting through is exp(-2 density)
+        let material = Arc::new(Isotropic::new(Vec3(0.5, 0.5, 0.5)));
+        let density = 0.4;
+        let medium = ConstantMedium::new(
+            Arc::new(Cuboid::new(
+                Vec3(-10.0, -10.0, -1.0),
+                Vec3(10.0, 10.0, 1.0),
+                material.clone(),
+            )),
+            density,
+            material,
+        );
+        // the same ray always scatters at the same point, so vary them
+        let count = 20_000;
+        let through = (0..count)
+            .filter(|idx| {
+                let ray = Ray::new(Vec3(*idx as f64 * 1e-4, 0.0, 5.0), Vec3(0.0, 0.0, -1.0));
+                medium.hit(ray, 0.0, f64::INFINITY).is_none()
+            })
+            .count();
+        let expected = (-2.0 * density).exp();
+        assert!(
+            (through as f64 / count as f64 - expected).abs() < 0.02,
+            "{} got through",
+            through
+        );
+    }
+
+    #[test]
+    fn rays_starting_inside_can_scatter() {
+        let medium = unit_sphere_medium(1e9);
+        let ray = Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0));
+        let hit = medium.hit(ray, 0.001, f64::INFINITY).unwrap();
+        assert!(hit.ray_t < 0.01);
+    }
+
+    #[test]
+    fn respects_t_max() {
+        let medium = unit_sphere_medium(1e9);
+        let ray = Ray::new(Vec3(0.0, 0.0, 5.0), Vec3(0.0, 0.0, -1.0));
+        assert!(medium.hit(ray, 0.0, 3.0).is_none());
+    }
+
+    #[test]
+    fn isotropic_scatters_evenly_from_the_hit_point() {
+        let medium = unit_sphere_medium(1e9);
+        let ray = Ray::new(Vec3(0.0, 0.0, 5.0), Vec3(0.0, 0.0, -1.0)).with_time(0.5);
+        let hit = medium.hit(ray, 0.0, f64::INFINITY).unwrap();
+        let mut sum = Vec3(0.0, 0.0, 0.0);
+        let mut sampler = IndependentSampler::new(0);
+        for _ in 0..10_000 {
+            let scattered = hit
+                .material
+                .scatter(&hit, &mut sampler)
+                .unwrap()
+                .scattered_ray;
+            assert_eq!(scattered.origin, hit.hit_point);
+            assert_eq!(scattered.time, 0.5);
+            assert!((scattered.vector.length() - 1.0).abs() < 1e-9);
+            sum += scattered.vector;
+        }
+        // no preferred direction, e.g. back along the ray
+        assert!((sum / 10_000.0).length() < 0.05);
+    }
+}
@@ -0,0 +1,545 @@
+use crate::hittable_list::HittableList;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::triangle::{Face, MeshData, TriangleMesh};
+use crate::vec3::Vec3;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub struct ObjError {
+    pub file: PathBuf,
+    // None for errors which aren't about a particular line, e.g. failing to
+    // read the file at all
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.file.display(), line, self.message),
+            None => write!(f, "{}: {}", self.file.display(), self.message),
+        }
+    }
+}
+
+impl Error for ObjError {}
+
+// Where we are in the file being parsed, for error reporting.
+struct Location<'a> {
+    file: &'a Path,
+    line: usize,
+}
+
+impl<'a> Location<'a> {
+    fn error(&self, message: impl Into<String>) -> ObjError {
+        ObjError {
+            file: self.file.to_path_buf(),
+            line: Some(self.line),
+            message: message.into(),
+        }
+    }
+
+    fn parse_f64(&self, token: &str) -> Result<f64, ObjError> {
+        token
+            .parse()
+            .map_err(|_| self.error(format!("invalid number '{}'", token)))
+    }
+
+    fn parse_vec3(&self, keyword: &str, args: &[&str]) -> Result<Vec3, ObjError> {
+        match args {
+            [x, y, z, ..] => Ok(Vec3(
+                self.parse_f64(x)?,
+                self.parse_f64(y)?,
+                self.parse_f64(z)?,
+            )),
+            _ => Err(self.error(format!("'{}' needs three numbers", keyword))),
+        }
+    }
+
+    fn parse_single(&self, keyword: &str, args: &[&str]) -> Result<f64, ObjError> {
+        match args {
+            [value] => self.parse_f64(value),
+            _ => Err(self.error(format!("'{}' needs exactly one number", keyword))),
+        }
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|err| ObjError {
+        file: path.to_path_buf(),
+        line: None,
+        message: err.to_string(),
+    })
+}
+
+// Splits a file into numbered lines of whitespace separated tokens, skipping
+// blank lines and comments.
+fn statements(source: &str) -> impl Iterator<Item = (usize, Vec<&str>)> {
+    source.lines().enumerate().filter_map(|(idx, line)| {
+        let line = line.split('#').next().unwrap_or("");
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.is_empty() {
+            None
+        } else {
+            Some((idx + 1, tokens))
+        }
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MtlMaterial {
+    diffuse: Vec3,
+    specular: Vec3,
+    specular_exponent: Option<f64>,
+    // None unless the file gives Ni
+    refractive_index: Option<f64>,
+    dissolve: f64,
+    emission: Vec3,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self {
+            diffuse: Vec3(0.8, 0.8, 0.8),
+            specular: Vec3(0.0, 0.0, 0.0),
+            specular_exponent: None,
+            refractive_index: None,
+            dissolve: 1.0,
+            emission: Vec3(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+// Many exporters write d for glass but leave out Ni, and an index of 1 would
+// make it invisible, so assume ordinary glass.
+const DEFAULT_REFRACTIVE_INDEX: f64 = 1.5;
+
+fn max_component(vec: Vec3) -> f64 {
+    vec.0.max(vec.1).max(vec.2)
+}
+
+impl MtlMaterial {
+    fn refractive_index(&self) -> f64 {
+        self.refractive_index.unwrap_or(DEFAULT_REFRACTIVE_INDEX)
+    }
+
+    // MTL describes a Phong-style mix of terms, whereas each of our materials
+    // does just one thing, so we pick whichever term dominates.
+    fn to_material(self) -> Arc<dyn Material + Send + Sync> {
+        if max_component(self.emission) > 0.0 {
+            Arc::new(DiffuseLight::new(self.emission))
+        } else if self.dissolve < 1.0 {
+            Arc::new(Dielectric::new(self.diffuse, self.refractive_index()))
+        } else if max_component(self.specular) > max_component(self.diffuse) {
+            // a rough conversion from the Phong exponent to a fuzz radius
+            let fuzz = self
+                .specular_exponent
+                .map_or(0.0, |exponent| (2.0 / (exponent + 2.0)).sqrt());
+            Arc::new(Metal::new(self.specular, fuzz.min(1.0)))
+        } else {
+            Arc::new(Lambertian::new(self.diffuse))
+        }
+    }
+}
+
+fn load_mtl(path: &Path) -> Result<HashMap<String, MtlMaterial>, ObjError> {
+    let source = read_file(path)?;
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+    for (line, tokens) in statements(&source) {
+        let location = Location { file: path, line };
+        let keyword = tokens[0];
+        let args = &tokens[1..];
+        if keyword == "newmtl" {
+            if args.len() != 1 {
+                return Err(location.error("'newmtl' needs exactly one name"));
+            }
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material);
+            }
+            current = Some((args[0].to_string(), MtlMaterial::default()));
+            continue;
+        }
+        let material = match current.as_mut() {
+            Some((_, material)) => material,
+            None => {
+                return Err(location.error(format!("'{}' before any 'newmtl'", keyword)));
+            }
+        };
+        match keyword {
+            "Kd" => material.diffuse = location.parse_vec3(keyword, args)?,
+            "Ks" => material.specular = location.parse_vec3(keyword, args)?,
+            "Ke" => material.emission = location.parse_vec3(keyword, args)?,
+            "Ns" => material.specular_exponent = Some(location.parse_single(keyword, args)?),
+            "Ni" => material.refractive_index = Some(location.parse_single(keyword, args)?),
+            "d" => material.dissolve = location.parse_single(keyword, args)?,
+            "Tr" => material.dissolve = 1.0 - location.parse_single(keyword, args)?,
+            // texture maps, illumination models etc. aren't supported
+            _ => {}
+        }
+    }
+    if let Some((name, material)) = current {
+        materials.insert(name, material);
+    }
+    Ok(materials)
+}
+
+// Converts a 1-based (or negative, relative to the end) OBJ index into an
+// index into a buffer of the given length.
+fn resolve_index(
+    location: &Location,
+    token: &str,
+    buffer_len: usize,
+    kind: &str,
+) -> Result<usize, ObjError> {
+    let idx: i64 = token
+        .parse()
+        .map_err(|_| location.error(format!("invalid {} index '{}'", kind, token)))?;
+    let resolved = match idx {
+        0 => return Err(location.error(format!("{} index cannot be 0", kind))),
+        idx if idx > 0 => idx - 1,
+        idx => buffer_len as i64 + idx,
+    };
+    if resolved < 0 || resolved >= buffer_len as i64 {
+        return Err(location.error(format!("{} index {} is out of range", kind, idx)));
+    }
+    Ok(resolved as usize)
+}
+
+struct FaceVertex {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+// Parses one of "v", "v/vt", "v//vn" or "v/vt/vn".
+fn parse_face_vertex(
+    location: &Location,
+    token: &str,
+    mesh: &MeshData,
+) -> Result<FaceVertex, ObjError> {
+    let mut parts = token.split('/');
+    let position = resolve_index(
+        location,
+        parts.next().unwrap_or(""),
+        mesh.positions.len(),
+        "vertex",
+    )?;
+    let uv = match parts.next() {
+        None | Some("") => None,
+        Some(part) => Some(resolve_index(location, part, mesh.uvs.len(), "texture")?),
+    };
+    let normal = match parts.next() {
+        None | Some("") => None,
+        Some(part) => Some(resolve_index(location, part, mesh.normals.len(), "normal")?),
+    };
+    if parts.next().is_some() {
+        return Err(location.error(format!("invalid face vertex '{}'", token)));
+    }
+    Ok(FaceVertex {
+        position,
+        uv,
+        normal,
+    })
+}
+
+// A run of consecutive faces sharing a group and material.
+struct FaceRun {
+    material: Option<String>,
+    faces: Range<usize>,
+}
+
+// Loads a Wavefront OBJ file (and any MTL files it references) as one
+// triangle mesh per group and material. Faces with no material use
+// default_material.
+pub fn load_obj(
+    path: &Path,
+    default_material: Arc<dyn Material + Send + Sync>,
+) -> Result<HittableList, ObjError> {
+    let source = read_file(path)?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut mesh = MeshData::default();
+    let mut mtl_materials = HashMap::new();
+    let mut runs = vec![];
+    let mut current_material: Option<String> = None;
+    let mut run_start = 0;
+
+    for (line, tokens) in statements(&source) {
+        let location = Location { file: path, line };
+        let keyword = tokens[0];
+        let args = &tokens[1..];
+        match keyword {
+            "v" => mesh.positions.push(location.parse_vec3(keyword, args)?),
+            "vn" => mesh.normals.push(location.parse_vec3(keyword, args)?),
+            "vt" => match args {
+                [u] => mesh.uvs.push((location.parse_f64(u)?, 0.0)),
+                [u, v, ..] => mesh
+                    .uvs
+                    .push((location.parse_f64(u)?, location.parse_f64(v)?)),
+                [] => return Err(location.error("'vt' needs at least one number")),
+            },
+            "f" => {
+                if args.len() < 3 {
+                    return Err(location.error("a face needs at least three vertices"));
+                }
+                let vertices = args
+                    .iter()
+                    .map(|token| parse_face_vertex(&location, token, &mesh))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let all_have_uvs = vertices.iter().all(|vertex| vertex.uv.is_some());
+                let all_have_normals = vertices.iter().all(|vertex| vertex.normal.is_some());
+                // triangulate polygons as a fan around the first vertex
+                for idx in 1..vertices.len() - 1 {
+                    let corners = [&vertices[0], &vertices[idx], &vertices[idx + 1]];
+                    mesh.faces.push(Face {
+                        positions: corners.map(|vertex| vertex.position),
+                        uvs: all_have_uvs.then(|| corners.map(|vertex| vertex.uv.unwrap())),
+                        normals: all_have_normals
+                            .then(|| corners.map(|vertex| vertex.normal.unwrap())),
+                    });
+                }
+            }
+            "g" | "o" | "usemtl" => {
+                if mesh.faces.len() > run_start {
+                    runs.push(FaceRun {
+                        material: current_material.clone(),
+                        faces: run_start..mesh.faces.len(),
+                    });
+                    run_start = mesh.faces.len();
+                }
+                if keyword == "usemtl" {
+                    let name = match args {
+                        [name] => name,
+                        _ => return Err(location.error("'usemtl' needs exactly one name")),
+                    };
+                    if !mtl_materials.contains_key(*name) {
+                        return Err(location.error(format!("unknown material '{}'", name)));
+                    }
+                    current_material = Some(name.to_string());
+                }
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    return Err(location.error("'mtllib' needs a file name"));
+                }
+                for mtl_file in args {
+                    mtl_materials.extend(load_mtl(&directory.join(mtl_file))?);
+                }
+            }
+            // smoothing groups, lines, free-form geometry etc. aren't supported
+            _ => {}
+        }
+    }
+    if mesh.faces.len() > run_start {
+        runs.push(FaceRun {
+            material: current_material,
+            faces: run_start..mesh.faces.len(),
+        });
+    }
+
+    let mesh = Arc::new(mesh);
+    let mut materials: HashMap<String, Arc<dyn Material + Send + Sync>> = HashMap::new();
+    let mut list = HittableList::new();
+    for run in runs {
+        let material = match run.material {
+            Some(name) => Arc::clone(
+                materials
+                    .entry(name.clone())
+                    .or_insert_with(|| mtl_materials[&name].to_material()),
+            ),
+            None => Arc::clone(&default_material),
+        };
+        list.add(TriangleMesh::from_faces(
+            Arc::clone(&mesh),
+            run.faces,
+            material,
+        ));
+    }
+    Ok(list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::ray::Ray;
+    use crate::utils::TempDir;
+
+    // Writes the given files into a fresh temporary directory, which lasts
+    // as long as the returned TempDir.
+    fn write_files(test_name: &str, files: &[(&str, &str)]) -> TempDir {
+        let directory = TempDir::new(&format!("obj-{}", test_name));
+        for (name, contents) in files {
+            fs::write(directory.path().join(name), contents).unwrap();
+        }
+        directory
+    }
+
+    fn default_material() -> Arc<dyn Material + Send + Sync> {
+        Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5)))
+    }
+
+    fn load_error(test_name: &str, obj: &str) -> ObjError {
+        let directory = write_files(test_name, &[("model.obj", obj)]);
+        match load_obj(&directory.path().join("model.obj"), default_material()) {
+            Ok(_) => panic!("expected an error"),
+            Err(err) => err,
+        }
+    }
+
+    fn ray_down_z_through(x: f64, y: f64) -> Ray {
+        Ray::new(Vec3(x, y, 5.0), Vec3(0.0, 0.0, -1.0))
+    }
+
+    #[test]
+    fn loads_and_triangulates_a_quad() {
+        let directory = write_files(
+            "quad",
+            &[(
+                "model.obj",
+                "# a unit square\n\
+                 v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+                 vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+                 vn 0 0 1\n\
+                 f 1/1/1 2/2/1 3/3/1 4/4/1\n",
+            )],
+        );
+        let list = load_obj(&directory.path().join("model.obj"), default_material()).unwrap();
+        let hit = list
+            .hit(ray_down_z_through(0.25, 0.75), 0.0, f64::INFINITY)
+            .unwrap();
+        assert!((hit.u - 0.25).abs() < 1e-12);
+        assert!((hit.v - 0.75).abs() < 1e-12);
+        assert_eq!(hit.normal, Vec3(0.0, 0.0, 1.0));
+        assert!(list
+            .hit(ray_down_z_through(1.5, 0.5), 0.0, f64::INFINITY)
+            .is_none());
+    }
+
+    #[test]
+    fn supports_negative_indices_and_groups() {
+        let directory = write_files(
+            "negative",
+            &[(
+                "model.obj",
+                "g first\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\n\
+                 g second\nv 0 0 1\nv 1 0 1\nv 0 1 1\nf -3 -2 -1\n",
+            )],
+        );
+        let list = load_obj(&directory.path().join("model.obj"), default_material()).unwrap();
+        assert_eq!(list.objects().len(), 2);
+        let hit = list
+            .hit(ray_down_z_through(0.2, 0.2), 0.0, f64::INFINITY)
+            .unwrap();
+        assert_eq!(hit.hit_point, Vec3(0.2, 0.2, 1.0));
+    }
+
+    #[test]
+    fn splits_meshes_by_material() {
+        let directory = write_files(
+            "materials",
+            &[
+                (
+                    "model.obj",
+                    "mtllib model.mtl\n\
+                     v 0 0 0\nv 1 0 0\nv 0 1 0\n\
+                     usemtl light\nf 1 2 3\nusemtl glass\nf 1 2 3\n",
+                ),
+                (
+                    "model.mtl",
+                    "newmtl light\nKd 0 0 0\nKe 4 4 4\n\
+                     newmtl glass\nKd 1 1 1\nNi 1.5\nd 0.1\n",
+                ),
+            ],
+        );
+        let list = load_obj(&directory.path().join("model.obj"), default_material()).unwrap();
+        assert_eq!(list.objects().len(), 2);
+    }
+
+    #[test]
+    fn maps_mtl_terms_to_materials() {
+        let directory = write_files(
+            "mtl",
+            &[(
+                "model.mtl",
+                "newmtl matte\nKd 0.5 0.2 0.1\n\
+                 newmtl shiny\nKd 0.1 0.1 0.1\nKs 0.9 0.9 0.9\nNs 1000\n",
+            )],
+        );
+        let materials = load_mtl(&directory.path().join("model.mtl")).unwrap();
+        assert_eq!(materials["matte"].diffuse, Vec3(0.5, 0.2, 0.1));
+        assert_eq!(materials["shiny"].specular, Vec3(0.9, 0.9, 0.9));
+        assert_eq!(materials["shiny"].specular_exponent, Some(1000.0));
+    }
+
+    #[test]
+    fn transparent_mtl_materials_default_to_glass() {
+        let directory = write_files(
+            "mtl-glass",
+            &[(
+                "model.mtl",
+                "newmtl glass\nKd 0.9 0.95 1\nd 0.5\n\
+                 newmtl water\nd 0.5\nNi 1.33\n",
+            )],
+        );
+        let materials = load_mtl(&directory.path().join("model.mtl")).unwrap();
+        assert_eq!(materials["glass"].refractive_index, None);
+        assert_eq!(materials["glass"].refractive_index(), 1.5);
+        assert_eq!(materials["glass"].diffuse, Vec3(0.9, 0.95, 1.0));
+        assert_eq!(materials["water"].refractive_index(), 1.33);
+    }
+
+    #[test]
+    fn reports_malformed_vertex_with_file_and_line() {
+        let err = load_error("malformed", "v 0 0 0\nv 1 zero 0\n");
+        assert_eq!(err.line, Some(2));
+        assert!(err.file.ends_with("model.obj"));
+        assert!(err
+            .to_string()
+            .ends_with("model.obj:2: invalid number 'zero'"));
+    }
+
+    #[test]
+    fn reports_out_of_range_index() {
+        let err = load_error("range", "v 0 0 0\nv 1 0 0\nv 0 1 0\n\nf 1 2 4\n");
+        assert_eq!(err.line, Some(5));
+        assert_eq!(err.message, "vertex index 4 is out of range");
+    }
+
+    #[test]
+    fn reports_unknown_material() {
+        let err = load_error("unknown-material", "usemtl nope\n");
+        assert_eq!(err.line, Some(1));
+        assert_eq!(err.message, "unknown material 'nope'");
+    }
+
+    #[test]
+    fn reports_errors_in_mtl_file_against_that_file() {
+        let directory = write_files(
+            "bad-mtl",
+            &[
+                ("model.obj", "mtllib model.mtl\n"),
+                ("model.mtl", "newmtl a\nKd 1 1\n"),
+            ],
+        );
+        let err = load_obj(&directory.path().join("model.obj"), default_material())
+            .err()
+            .unwrap();
+        assert!(err.file.ends_with("model.mtl"));
+        assert_eq!(err.line, Some(2));
+    }
+
+    #[test]
+    fn reports_missing_file_without_line() {
+        let err = load_obj(Path::new("/definitely/not/here.obj"), default_material())
+            .err()
+            .unwrap();
+        assert_eq!(err.line, None);
+    }
+}
@@ -0,0 +1,322 @@
+use crate::framebuffer::Framebuffer;
+use std::io::{self, Write};
+
+const MAGIC: [u8; 4] = [0x76, 0x2F, 0x31, 0x01];
+// single-part scanline image
+const VERSION: [u8; 4] = [2, 0, 0, 0];
+// channels have to be listed in alphabetical order
+const CHANNEL_NAMES: [&str; 3] = ["B", "G", "R"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExrPixelType {
+    Half,
+    Float,
+}
+
+impl ExrPixelType {
+    fn code(self) -> i32 {
+        match self {
+            Self::Half => 1,
+            Self::Float => 2,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::Half => 2,
+            Self::Float => 4,
+        }
+    }
+}
+
+// Converts to IEEE 754 half precision, rounding to nearest even. Values too
+// large for a half become infinity.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x7F_FFFF;
+    if exponent == 0xFF {
+        let nan_bit = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7C00 | nan_bit;
+    }
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1F {
+        return sign | 0x7C00;
+    }
+    if half_exponent <= 0 {
+        // too small for a normal half, so make a subnormal one
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - half_exponent) as u32;
+        let half_mantissa = mantissa >> shift;
+        let remainder = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round_up = remainder > halfway || (remainder == halfway && half_mantissa & 1 == 1);
+        return sign | (half_mantissa + round_up as u32) as u16;
+    }
+    let half = ((half_exponent as u32) << 10) | (mantissa >> 13);
+    let remainder = mantissa & 0x1FFF;
+    let round_up = remainder > 0x1000 || (remainder == 0x1000 && half & 1 == 1);
+    // a carry out of the mantissa correctly bumps the exponent
+    sign | (half + round_up as u32) as u16
+}
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, attribute_type: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(attribute_type.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+fn box2i(width: u32, height: u32) -> Vec<u8> {
+    [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|coord| coord.to_le_bytes())
+        .collect()
+}
+
+// Uncompressed scanline OpenEXR, with one scanline per block.
+pub fn write_exr(
+    out: &mut dyn Write,
+    framebuffer: &Framebuffer,
+    pixel_type: ExrPixelType,
+) -> io::Result<()> {
+    let (width, height) = (framebuffer.width(), framebuffer.height());
+    let mut channels = vec![];
+    for name in CHANNEL_NAMES {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&pixel_type.code().to_le_bytes());
+        // pLinear and reserved bytes, then x and y sampling
+        channels.extend_from_slice(&[0, 0, 0, 0]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+
+    let mut header = vec![];
+    header.extend_from_slice(&MAGIC);
+    header.extend_from_slice(&VERSION);
+    write_attribute(&mut header, "channels", "chlist", &channels);
+    write_attribute(&mut header, "compression", "compression", &[0]);
+    write_attribute(&mut header, "dataWindow", "box2i", &box2i(width, height));
+    write_attribute(&mut header, "displayWindow", "box2i", &box2i(width, height));
+    // increasing y
+    write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    write_attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    write_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    write_attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+    out.write_all(&header)?;
+
+    // the offset table points at the start of each scanline block
+    let line_size = width as usize * CHANNEL_NAMES.len() * pixel_type.size();
+    let block_size = 8 + line_size;
+    let first_block = header.len() + 8 * height as usize;
+    for y in 0..height as usize {
+        out.write_all(&((first_block + y * block_size) as u64).to_le_bytes())?;
+    }
+
+    let mut block = Vec::with_capacity(block_size);
+    for (y, row) in framebuffer.rows().enumerate() {
+        block.clear();
+        block.extend_from_slice(&(y as i32).to_le_bytes());
+        block.extend_from_slice(&(line_size as i32).to_le_bytes());
+        for channel in CHANNEL_NAMES {
+            for pixel in row {
+                let value = match channel {
+                    "R" => pixel.r(),
+                    "G" => pixel.g(),
+                    _ => pixel.b(),
+                } as f32;
+                match pixel_type {
+                    ExrPixelType::Half => block.extend_from_slice(&f32_to_f16(value).to_le_bytes()),
+                    ExrPixelType::Float => block.extend_from_slice(&value.to_le_bytes()),
+                }
+            }
+        }
+        out.write_all(&block)?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use std::collections::HashMap;
+
+    fn f16_to_f32(half: u16) -> f32 {
+        let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+        let exponent = ((half >> 10) & 0x1F) as i32;
+        let mantissa = (half & 0x3FF) as f32;
+        match exponent {
+            0 => sign * mantissa * 2f32.powi(-24),
+            0x1F if mantissa == 0.0 => sign * f32::INFINITY,
+            0x1F => f32::NAN,
+            _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+        }
+    }
+
+    fn read_i32(bytes: &[u8], position: usize) -> i32 {
+        i32::from_le_bytes(bytes[position..position + 4].try_into().unwrap())
+    }
+
+    fn read_string(bytes: &[u8], position: &mut usize) -> String {
+        let end = *position + bytes[*position..].iter().position(|&b| b == 0).unwrap();
+        let string = String::from_utf8(bytes[*position..end].to_vec()).unwrap();
+        *position = end + 1;
+        string
+    }
+
+    // A minimal reader for what `write_exr` produces, returning the header
+    // attributes and the pixels as floats.
+    fn read_exr(bytes: &[u8]) -> (HashMap<String, Vec<u8>>, Vec<[f32; 3]>) {
+        assert_eq!(bytes[..4], MAGIC);
+        assert_eq!(bytes[4..8], VERSION);
+        let mut position = 8;
+        let mut attributes = HashMap::new();
+        loop {
+            let name = read_string(bytes, &mut position);
+            if name.is_empty() {
+                break;
+            }
+            read_string(bytes, &mut position);
+            let size = read_i32(bytes, position) as usize;
+            position += 4;
+            attributes.insert(name, bytes[position..position + size].to_vec());
+            position += size;
+        }
+        let window = &attributes["dataWindow"];
+        let width = (read_i32(window, 8) + 1) as usize;
+        let height = (read_i32(window, 12) + 1) as usize;
+        let pixel_type = read_i32(&attributes["channels"], 2);
+        let sample_size = if pixel_type == 1 { 2 } else { 4 };
+
+        let mut pixels = vec![[0.0; 3]; width * height];
+        for line in 0..height {
+            let offset_position = position + line * 8;
+            let offset = u64::from_le_bytes(
+                bytes[offset_position..offset_position + 8]
+                    .try_into()
+                    .unwrap(),
+            ) as usize;
+            let y = read_i32(bytes, offset) as usize;
+            assert_eq!(
+                read_i32(bytes, offset + 4) as usize,
+                width * 3 * sample_size
+            );
+            for (channel_idx, component) in [2, 1, 0].into_iter().enumerate() {
+                for x in 0..width {
+                    let start = offset + 8 + (channel_idx * width + x) * sample_size;
+                    let sample = &bytes[start..start + sample_size];
+                    pixels[y * width + x][component] = if sample_size == 2 {
+                        f16_to_f32(u16::from_le_bytes(sample.try_into().unwrap()))
+                    } else {
+                        f32::from_le_bytes(sample.try_into().unwrap())
+                    };
+                }
+            }
+        }
+        (attributes, pixels)
+    }
+
+    fn test_pixels() -> Vec<Color> {
+        vec![
+            Color::new(0.0, 0.5, 1.0),
+            Color::new(2.0, 16.5, 1000.0),
+            Color::new(0.1, 0.2, 0.3),
+            Color::new(-0.25, 0.0001, 65504.0),
+            Color::new(1e-5, 3.25, 7.0),
+            Color::new(0.75, 0.875, 0.9375),
+        ]
+    }
+
+    #[test]
+    fn converts_to_half() {
+        assert_eq!(f32_to_f16(0.0), 0);
+        assert_eq!(f32_to_f16(-0.0), 0x8000);
+        assert_eq!(f32_to_f16(1.0), 0x3C00);
+        assert_eq!(f32_to_f16(-2.0), 0xC000);
+        assert_eq!(f32_to_f16(65504.0), 0x7BFF);
+        assert_eq!(f32_to_f16(1e6), 0x7C00);
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7C00);
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+        // smallest subnormal
+        assert_eq!(f32_to_f16(2f32.powi(-24)), 1);
+        assert_eq!(f32_to_f16(2f32.powi(-26)), 0);
+        // halfway between 1 and the next half rounds to even
+        assert_eq!(f32_to_f16(1.0 + 2f32.powi(-11)), 0x3C00);
+        assert_eq!(f32_to_f16(1.0 + 3.0 * 2f32.powi(-11)), 0x3C02);
+    }
+
+    #[test]
+    fn round_trips_float_image() {
+        let pixels = test_pixels();
+        let mut bytes = vec![];
+        write_exr(
+            &mut bytes,
+            &Framebuffer::from_pixels(3, 2, &pixels),
+            ExrPixelType::Float,
+        )
+        .unwrap();
+        let (attributes, decoded) = read_exr(&bytes);
+        assert_eq!(attributes["compression"], [0]);
+        assert_eq!(attributes["lineOrder"], [0]);
+        assert_eq!(attributes["displayWindow"], attributes["dataWindow"]);
+        for (decoded, original) in decoded.iter().zip(&pixels) {
+            assert_eq!(
+                *decoded,
+                [
+                    original.r() as f32,
+                    original.g() as f32,
+                    original.b() as f32
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips_half_image() {
+        let pixels = test_pixels();
+        let mut bytes = vec![];
+        write_exr(
+            &mut bytes,
+            &Framebuffer::from_pixels(2, 3, &pixels),
+            ExrPixelType::Half,
+        )
+        .unwrap();
+        let (_, decoded) = read_exr(&bytes);
+        for (decoded, original) in decoded.iter().zip(&pixels) {
+            for (decoded, original) in
+                decoded
+                    .iter()
+                    .zip([original.r(), original.g(), original.b()])
+            {
+                // halves have an 11 bit significand
+                let tolerance = (original.abs() / 1024.0).max(1e-7);
+                assert!(
+                    (*decoded as f64 - original).abs() <= tolerance,
+                    "{} vs {}",
+                    decoded,
+                    original
+                );
+            }
+        }
+    }
+}
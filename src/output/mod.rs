@@ -0,0 +1,242 @@
+mod exr;
+mod pfm;
+mod png;
+mod ppm;
+mod rgbe;
+
+use crate::framebuffer::Framebuffer;
+use crate::tonemap::ToneMapping;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+pub use exr::ExrPixelType;
+pub use png::BitDepth;
+pub use ppm::PpmEncoding;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    // Portable pixmap
+    Ppm(PpmEncoding),
+    Png(BitDepth),
+    // The formats below store linear, unclamped color.
+    // Portable float map
+    Pfm,
+    // Radiance RGBE
+    Hdr,
+    // Uncompressed scanline OpenEXR
+    Exr(ExrPixelType),
+}
+
+impl OutputFormat {
+    // PNGs are 8-bit and EXRs half float unless changed with `with_bit_depth`.
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("ppm") => Ok(Self::Ppm(PpmEncoding::Ascii)),
+            Some("png") => Ok(Self::Png(BitDepth::Eight)),
+            Some("pfm") => Ok(Self::Pfm),
+            Some("hdr") => Ok(Self::Hdr),
+            Some("exr") => Ok(Self::Exr(ExrPixelType::Half)),
+            Some(extension) => Err(format!("unsupported output format '.{}'", extension)),
+            None => Err(format!(
+                "can't tell the output format of '{}' without a file extension",
+                path.display()
+            )),
+        }
+    }
+
+    // Bits per channel, where a format supports more than one.
+    pub fn with_bit_depth(self, bits: u32) -> Result<Self, String> {
+        match (self, bits) {
+            (Self::Png(_), 8) => Ok(Self::Png(BitDepth::Eight)),
+            (Self::Png(_), 16) => Ok(Self::Png(BitDepth::Sixteen)),
+            (Self::Exr(_), 16) => Ok(Self::Exr(ExrPixelType::Half)),
+            (Self::Exr(_), 32) => Ok(Self::Exr(ExrPixelType::Float)),
+            (Self::Ppm(_), 8) | (Self::Pfm, 32) | (Self::Hdr, 8) => Ok(self),
+            _ => Err(format!(
+                "{} output doesn't support a bit depth of {}",
+                self.name(),
+                bits
+            )),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Ppm(_) => "PPM",
+            Self::Png(_) => "PNG",
+            Self::Pfm => "PFM",
+            Self::Hdr => "Radiance HDR",
+            Self::Exr(_) => "OpenEXR",
+        }
+    }
+}
+
+// Writes the framebuffer's linear colors. Tone mapping only applies to the
+// low dynamic range formats - the others get the radiance unchanged.
+pub fn write_image(
+    out: &mut dyn Write,
+    format: OutputFormat,
+    framebuffer: &Framebuffer,
+    tone_mapping: &ToneMapping,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Ppm(encoding) => ppm::write_ppm(out, framebuffer, tone_mapping, encoding),
+        OutputFormat::Png(bit_depth) => png::write_png(out, framebuffer, tone_mapping, bit_depth),
+        OutputFormat::Pfm => pfm::write_pfm(out, framebuffer),
+        OutputFormat::Hdr => rgbe::write_hdr(out, framebuffer),
+        OutputFormat::Exr(pixel_type) => exr::write_exr(out, framebuffer, pixel_type),
+    }
+}
+
+// An image read back in, e.g. for a texture. Samples are RGB, from 0 to
+// max_value and still sRGB encoded.
+pub struct DecodedImage {
+    pub width: u32,
+    pub height: u32,
+    pub max_value: u16,
+    pub pixels: Vec<[u16; 3]>,
+}
+
+// Reads a PNG or PPM image, depending on the file's extension.
+pub fn read_image(path: &Path) -> io::Result<DecodedImage> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("png") => png::read_png(&fs::read(path)?),
+        Some("ppm") => ppm::read_ppm(&fs::read(path)?),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "only PNG and PPM images can be read",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::tonemap::ToneMapOperator;
+    use crate::utils::TempDir;
+
+    #[test]
+    fn infers_format_from_extension() {
+        assert_eq!(
+            OutputFormat::from_path(Path::new("img.ppm")),
+            Ok(OutputFormat::Ppm(PpmEncoding::Ascii))
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("IMG.PPM")),
+            Ok(OutputFormat::Ppm(PpmEncoding::Ascii))
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("img.png")),
+            Ok(OutputFormat::Png(BitDepth::Eight))
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("img.pfm")),
+            Ok(OutputFormat::Pfm)
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("img.hdr")),
+            Ok(OutputFormat::Hdr)
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("img.exr")),
+            Ok(OutputFormat::Exr(ExrPixelType::Half))
+        );
+        assert!(OutputFormat::from_path(Path::new("img.gif")).is_err());
+        assert!(OutputFormat::from_path(Path::new("img")).is_err());
+    }
+
+    #[test]
+    fn checks_bit_depth_is_supported() {
+        assert_eq!(
+            OutputFormat::Png(BitDepth::Eight).with_bit_depth(16),
+            Ok(OutputFormat::Png(BitDepth::Sixteen))
+        );
+        assert_eq!(
+            OutputFormat::Exr(ExrPixelType::Half).with_bit_depth(32),
+            Ok(OutputFormat::Exr(ExrPixelType::Float))
+        );
+        let ppm = OutputFormat::Ppm(PpmEncoding::Ascii);
+        assert_eq!(ppm.with_bit_depth(8), Ok(ppm));
+        assert_eq!(OutputFormat::Pfm.with_bit_depth(32), Ok(OutputFormat::Pfm));
+        assert_eq!(
+            ppm.with_bit_depth(16),
+            Err("PPM output doesn't support a bit depth of 16".to_string())
+        );
+        assert!(OutputFormat::Png(BitDepth::Eight)
+            .with_bit_depth(32)
+            .is_err());
+        assert!(OutputFormat::Exr(ExrPixelType::Half)
+            .with_bit_depth(8)
+            .is_err());
+    }
+
+    #[test]
+    fn writes_png_through_write_image() {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.set(0, 0, Color::new(1.0, 0.25, 0.0));
+        let mut bytes = vec![];
+        write_image(
+            &mut bytes,
+            OutputFormat::Png(BitDepth::Sixteen),
+            &framebuffer,
+            &ToneMapping::default(),
+        )
+        .unwrap();
+        let image = png::read_png(&bytes).unwrap();
+        assert_eq!(
+            (image.width, image.height, image.max_value),
+            (3, 2, u16::MAX)
+        );
+        assert_eq!(image.pixels[0], [u16::MAX, 35199, 0]);
+    }
+
+    #[test]
+    fn reads_images_by_extension() {
+        let directory = TempDir::new("read-image");
+        let mut framebuffer = Framebuffer::new(2, 1);
+        framebuffer.set(1, 0, Color::new(1.0, 1.0, 1.0));
+        for (name, format) in [
+            ("img.png", OutputFormat::Png(BitDepth::Eight)),
+            ("img.ppm", OutputFormat::Ppm(PpmEncoding::Ascii)),
+        ] {
+            let mut bytes = vec![];
+            write_image(&mut bytes, format, &framebuffer, &ToneMapping::default()).unwrap();
+            fs::write(directory.path().join(name), bytes).unwrap();
+            let image = read_image(&directory.path().join(name)).unwrap();
+            assert_eq!((image.width, image.height, image.max_value), (2, 1, 255));
+            assert_eq!(image.pixels, [[0, 0, 0], [255, 255, 255]]);
+        }
+        assert!(read_image(&directory.path().join("img.gif")).is_err());
+    }
+
+    #[test]
+    fn tone_maps_only_low_dynamic_range_output() {
+        let mut framebuffer = Framebuffer::new(1, 1);
+        framebuffer.set(0, 0, Color::new(3.0, 1.0, 0.0));
+        let tone_mapping = ToneMapping {
+            operator: ToneMapOperator::Reinhard,
+            ..ToneMapping::default()
+        };
+
+        let mut bytes = vec![];
+        let format = OutputFormat::Ppm(PpmEncoding::Binary);
+        write_image(&mut bytes, format, &framebuffer, &tone_mapping).unwrap();
+        // 0.75 and 0.5 once sRGB encoded
+        assert_eq!(bytes[bytes.len() - 3..], [225, 188, 0]);
+
+        let mut bytes = vec![];
+        write_image(&mut bytes, OutputFormat::Pfm, &framebuffer, &tone_mapping).unwrap();
+        let red = f32::from_le_bytes(bytes[bytes.len() - 12..bytes.len() - 8].try_into().unwrap());
+        assert_eq!(red, 3.0);
+    }
+}
@@ -0,0 +1,49 @@
+use crate::framebuffer::Framebuffer;
+use std::io::{self, Write};
+
+// Portable float map: linear 32-bit floats, rows from the bottom of the image
+// upwards. A negative scale means the floats are little-endian.
+pub fn write_pfm(out: &mut dyn Write, framebuffer: &Framebuffer) -> io::Result<()> {
+    write!(
+        out,
+        "PF\n{} {}\n-1.0\n",
+        framebuffer.width(),
+        framebuffer.height()
+    )?;
+    for row in framebuffer.rows().rev() {
+        for pixel in row {
+            for component in [pixel.r(), pixel.g(), pixel.b()] {
+                out.write_all(&(component as f32).to_le_bytes())?;
+            }
+        }
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    #[test]
+    fn writes_bottom_row_first() {
+        let pixels = [
+            Color::new(1.0, 2.0, 3.0),
+            Color::new(4.0, 5.0, 6.0),
+            Color::new(7.5, -1.0, 100.25),
+            Color::new(0.0, 0.0, 0.0),
+        ];
+        let mut bytes = vec![];
+        write_pfm(&mut bytes, &Framebuffer::from_pixels(2, 2, &pixels)).unwrap();
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(bytes[..header.len()], header[..]);
+        let floats: Vec<f32> = bytes[header.len()..]
+            .chunks(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        assert_eq!(
+            floats,
+            [7.5, -1.0, 100.25, 0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0]
+        );
+    }
+}
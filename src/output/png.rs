@@ -0,0 +1,446 @@
+use super::DecodedImage;
+use crate::color::{DeepRenderColor, RenderColor};
+use crate::framebuffer::Framebuffer;
+use crate::tonemap::ToneMapping;
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use miniz_oxide::inflate::decompress_to_vec_zlib_with_limit;
+use std::io::{self, Write};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const COMPRESSION_LEVEL: u8 = 6;
+
+const COLOR_TYPE_GRAY: u8 = 0;
+const COLOR_TYPE_RGB: u8 = 2;
+const COLOR_TYPE_PALETTE: u8 = 3;
+const COLOR_TYPE_GRAY_ALPHA: u8 = 4;
+const COLOR_TYPE_RGBA: u8 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitDepth {
+    Eight,
+    Sixteen,
+}
+
+const fn make_crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+const CRC_TABLE: [u32; 256] = make_crc_table();
+
+// The CRC-32 used by PNG (and zip, gzip etc.), over several byte slices in
+// turn.
+fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = 0xFFFF_FFFF;
+    for part in parts {
+        for byte in part.iter() {
+            crc = CRC_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+        }
+    }
+    crc ^ 0xFFFF_FFFF
+}
+
+fn write_chunk(out: &mut dyn Write, chunk_type: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(chunk_type)?;
+    out.write_all(data)?;
+    out.write_all(&crc32(&[chunk_type, data]).to_be_bytes())
+}
+
+pub fn write_png(
+    out: &mut dyn Write,
+    framebuffer: &Framebuffer,
+    tone_mapping: &ToneMapping,
+    bit_depth: BitDepth,
+) -> io::Result<()> {
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&framebuffer.width().to_be_bytes());
+    header.extend_from_slice(&framebuffer.height().to_be_bytes());
+    header.push(match bit_depth {
+        BitDepth::Eight => 8,
+        BitDepth::Sixteen => 16,
+    });
+    header.push(COLOR_TYPE_RGB);
+    // compression method, filter method, no interlacing
+    header.extend_from_slice(&[0, 0, 0]);
+
+    let mut raw = vec![];
+    for row in framebuffer.rows() {
+        // every scanline starts with its filter type - we don't filter
+        raw.push(0);
+        for pixel in row {
+            let pixel = tone_mapping.map(*pixel);
+            match bit_depth {
+                BitDepth::Eight => raw.extend_from_slice(&RenderColor::from_color(pixel).rgb()),
+                BitDepth::Sixteen => {
+                    for sample in DeepRenderColor::from_color(pixel).rgb() {
+                        raw.extend_from_slice(&sample.to_be_bytes());
+                    }
+                }
+            }
+        }
+    }
+
+    out.write_all(&SIGNATURE)?;
+    write_chunk(out, b"IHDR", &header)?;
+    write_chunk(out, b"IDAT", &compress_to_vec_zlib(&raw, COMPRESSION_LEVEL))?;
+    write_chunk(out, b"IEND", &[])?;
+    out.flush()
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn paeth_predictor(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// Reverses the per-scanline filtering, returning just the sample bytes.
+fn unfilter(
+    data: &[u8],
+    height: usize,
+    stride: usize,
+    bytes_per_pixel: usize,
+) -> io::Result<Vec<u8>> {
+    if (stride + 1).checked_mul(height) != Some(data.len()) {
+        return Err(invalid_data("image data is the wrong size"));
+    }
+    let mut samples = vec![0u8; height * stride];
+    for row in 0..height {
+        let filter = data[row * (stride + 1)];
+        let line = &data[row * (stride + 1) + 1..(row + 1) * (stride + 1)];
+        let (previous_rows, current_rows) = samples.split_at_mut(row * stride);
+        let previous = if row == 0 {
+            None
+        } else {
+            Some(&previous_rows[(row - 1) * stride..])
+        };
+        let current = &mut current_rows[..stride];
+        for idx in 0..stride {
+            let a = if idx >= bytes_per_pixel {
+                current[idx - bytes_per_pixel]
+            } else {
+                0
+            };
+            let b = previous.map_or(0, |previous| previous[idx]);
+            let c = match previous {
+                Some(previous) if idx >= bytes_per_pixel => previous[idx - bytes_per_pixel],
+                _ => 0,
+            };
+            let prediction = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth_predictor(a, b, c),
+                _ => return Err(invalid_data(format!("unknown filter type {}", filter))),
+            };
+            current[idx] = line[idx].wrapping_add(prediction);
+        }
+    }
+    Ok(samples)
+}
+
+// Any alpha channel is dropped, and grayscale or palette images are expanded
+// to RGB.
+pub fn read_png(bytes: &[u8]) -> io::Result<DecodedImage> {
+    if !bytes.starts_with(&SIGNATURE) {
+        return Err(invalid_data("not a PNG file"));
+    }
+    let mut position = SIGNATURE.len();
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut compressed = vec![];
+    loop {
+        if position + 12 > bytes.len() {
+            return Err(invalid_data("unexpected end of file"));
+        }
+        let length = u32::from_be_bytes(bytes[position..position + 4].try_into().unwrap()) as usize;
+        let chunk_type = &bytes[position + 4..position + 8];
+        let data_end = position + 8 + length;
+        if data_end + 4 > bytes.len() {
+            return Err(invalid_data("unexpected end of file"));
+        }
+        let data = &bytes[position + 8..data_end];
+        let crc = u32::from_be_bytes(bytes[data_end..data_end + 4].try_into().unwrap());
+        if crc != crc32(&[chunk_type, data]) {
+            return Err(invalid_data("chunk has the wrong checksum"));
+        }
+        position = data_end + 4;
+        match chunk_type {
+            b"IHDR" => {
+                if data.len() != 13 {
+                    return Err(invalid_data("IHDR chunk is the wrong size"));
+                }
+                header = Some(data);
+            }
+            b"PLTE" => palette = data,
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            // the case of the first letter says whether a chunk is critical
+            _ if chunk_type[0].is_ascii_uppercase() => {
+                return Err(invalid_data(format!(
+                    "unsupported critical chunk {}",
+                    String::from_utf8_lossy(chunk_type)
+                )));
+            }
+            _ => {}
+        }
+    }
+
+    let header = header.ok_or_else(|| invalid_data("missing IHDR chunk"))?;
+    let width = u32::from_be_bytes(header[0..4].try_into().unwrap());
+    let height = u32::from_be_bytes(header[4..8].try_into().unwrap());
+    let bit_depth = header[8];
+    let color_type = header[9];
+    if header[12] != 0 {
+        return Err(invalid_data("interlaced images aren't supported"));
+    }
+    let channels = match (color_type, bit_depth) {
+        (COLOR_TYPE_PALETTE, 8) => 1,
+        (COLOR_TYPE_GRAY, 8 | 16) => 1,
+        (COLOR_TYPE_GRAY_ALPHA, 8 | 16) => 2,
+        (COLOR_TYPE_RGB, 8 | 16) => 3,
+        (COLOR_TYPE_RGBA, 8 | 16) => 4,
+        _ => {
+            return Err(invalid_data(format!(
+                "unsupported color type {} with bit depth {}",
+                color_type, bit_depth
+            )));
+        }
+    };
+    let bytes_per_sample = bit_depth as usize / 8;
+    let bytes_per_pixel = channels * bytes_per_sample;
+    // The header can't be trusted, so the sizes it gives are checked, and
+    // no more data is inflated than they call for.
+    let too_large = || invalid_data("image is too large");
+    let stride = (width as usize)
+        .checked_mul(bytes_per_pixel)
+        .ok_or_else(too_large)?;
+    let data_size = (stride + 1)
+        .checked_mul(height as usize)
+        .ok_or_else(too_large)?;
+    let data = decompress_to_vec_zlib_with_limit(&compressed, data_size)
+        .map_err(|err| invalid_data(format!("invalid image data: {:?}", err.status)))?;
+    let samples = unfilter(&data, height as usize, stride, bytes_per_pixel)?;
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    for pixel in samples.chunks(bytes_per_pixel) {
+        let sample = |channel: usize| {
+            let start = channel * bytes_per_sample;
+            if bytes_per_sample == 2 {
+                u16::from_be_bytes([pixel[start], pixel[start + 1]])
+            } else {
+                pixel[start] as u16
+            }
+        };
+        pixels.push(match color_type {
+            COLOR_TYPE_PALETTE => {
+                let idx = pixel[0] as usize * 3;
+                if idx + 3 > palette.len() {
+                    return Err(invalid_data("palette index out of range"));
+                }
+                [
+                    palette[idx] as u16,
+                    palette[idx + 1] as u16,
+                    palette[idx + 2] as u16,
+                ]
+            }
+            COLOR_TYPE_GRAY | COLOR_TYPE_GRAY_ALPHA => [sample(0); 3],
+            _ => [sample(0), sample(1), sample(2)],
+        });
+    }
+    Ok(DecodedImage {
+        width,
+        height,
+        max_value: ((1u32 << bit_depth) - 1) as u16,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    fn test_pixels(width: u32, height: u32) -> Vec<Color> {
+        let mut pixels = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(Color::new(
+                    x as f64 / width as f64,
+                    y as f64 / height as f64,
+                    // some out of range values, to check they get clamped
+                    (x + y) as f64 / 4.0 - 0.5,
+                ));
+            }
+        }
+        pixels
+    }
+
+    fn encode(width: u32, height: u32, pixels: &[Color], bit_depth: BitDepth) -> Vec<u8> {
+        let mut bytes = vec![];
+        write_png(
+            &mut bytes,
+            &Framebuffer::from_pixels(width, height, pixels),
+            &ToneMapping::default(),
+            bit_depth,
+        )
+        .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn crc_matches_standard_check_value() {
+        assert_eq!(crc32(&[b"1234", b"56789"]), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn round_trips_8_bit_image() {
+        let pixels = test_pixels(7, 5);
+        let image = read_png(&encode(7, 5, &pixels, BitDepth::Eight)).unwrap();
+        assert_eq!((image.width, image.height, image.max_value), (7, 5, 255));
+        for (decoded, original) in image.pixels.iter().zip(&pixels) {
+            assert_eq!(
+                decoded.map(|sample| sample as u8),
+                RenderColor::from_color(*original).rgb()
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips_16_bit_image() {
+        let pixels = test_pixels(4, 6);
+        let image = read_png(&encode(4, 6, &pixels, BitDepth::Sixteen)).unwrap();
+        assert_eq!(
+            (image.width, image.height, image.max_value),
+            (4, 6, u16::MAX)
+        );
+        for (decoded, original) in image.pixels.iter().zip(&pixels) {
+            assert_eq!(*decoded, DeepRenderColor::from_color(*original).rgb());
+        }
+    }
+
+    #[test]
+    fn writes_signature_and_header() {
+        let bytes = encode(3, 2, &test_pixels(3, 2), BitDepth::Sixteen);
+        assert_eq!(bytes[..8], SIGNATURE);
+        // IHDR length, then type
+        assert_eq!(bytes[8..16], [0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+        // width, height, bit depth, color type
+        assert_eq!(bytes[16..26], [0, 0, 0, 3, 0, 0, 0, 2, 16, 2]);
+        assert!(bytes.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]));
+    }
+
+    #[test]
+    fn decodes_all_filter_types() {
+        // a 2x5 grayscale image, each row using a different filter
+        let expected = [[10, 20], [30, 50], [40, 45], [100, 1], [7, 200]];
+        let mut raw = vec![];
+        let mut previous = [0u8, 0];
+        for (filter, row) in expected.iter().enumerate() {
+            raw.push(filter as u8);
+            for idx in 0..2 {
+                let a = if idx > 0 { row[idx - 1] } else { 0 };
+                let b = previous[idx];
+                let c = if idx > 0 { previous[idx - 1] } else { 0 };
+                let prediction = match filter {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => ((a as u16 + b as u16) / 2) as u8,
+                    _ => paeth_predictor(a, b, c),
+                };
+                raw.push(row[idx].wrapping_sub(prediction));
+            }
+            previous = *row;
+        }
+        let mut bytes = SIGNATURE.to_vec();
+        write_chunk(
+            &mut bytes,
+            b"IHDR",
+            &[0, 0, 0, 2, 0, 0, 0, 5, 8, 0, 0, 0, 0],
+        )
+        .unwrap();
+        write_chunk(&mut bytes, b"IDAT", &compress_to_vec_zlib(&raw, 6)).unwrap();
+        write_chunk(&mut bytes, b"IEND", &[]).unwrap();
+
+        let image = read_png(&bytes).unwrap();
+        let decoded: Vec<u16> = image.pixels.iter().map(|pixel| pixel[0]).collect();
+        let expected: Vec<u16> = expected
+            .iter()
+            .flatten()
+            .map(|&sample| sample as u16)
+            .collect();
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn rejects_corrupted_data() {
+        let mut bytes = encode(2, 2, &test_pixels(2, 2), BitDepth::Eight);
+        assert!(read_png(&bytes[1..]).is_err());
+        // flip a bit in the IHDR chunk so its checksum no longer matches
+        bytes[17] ^= 1;
+        assert!(read_png(&bytes).is_err());
+    }
+
+    fn png_with(width: u32, height: u32, color_type: u8, bit_depth: u8, raw: &[u8]) -> Vec<u8> {
+        let mut header = vec![];
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+        let mut bytes = SIGNATURE.to_vec();
+        write_chunk(&mut bytes, b"IHDR", &header).unwrap();
+        write_chunk(&mut bytes, b"IDAT", &compress_to_vec_zlib(raw, 6)).unwrap();
+        write_chunk(&mut bytes, b"IEND", &[]).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn rejects_hostile_headers_and_data() {
+        // sizes which overflow
+        let bytes = png_with(u32::MAX, u32::MAX, COLOR_TYPE_RGBA, 16, &[0; 16]);
+        assert_eq!(
+            read_png(&bytes).err().unwrap().to_string(),
+            "image is too large"
+        );
+        // far more data than a 1x1 image needs isn't inflated
+        let bytes = png_with(1, 1, COLOR_TYPE_GRAY, 8, &vec![0; 1 << 20]);
+        assert!(read_png(&bytes)
+            .err()
+            .unwrap()
+            .to_string()
+            .starts_with("invalid image data"));
+        // and a header claiming a huge image is refused for lack of data
+        let bytes = png_with(1 << 20, 1 << 20, COLOR_TYPE_RGB, 8, &[0; 16]);
+        assert!(read_png(&bytes).is_err());
+        let bytes = png_with(1, 1, COLOR_TYPE_GRAY, 8, &[0, 7]);
+        assert_eq!(read_png(&bytes).unwrap().pixels, [[7, 7, 7]]);
+    }
+}
@@ -0,0 +1,268 @@
+use super::DecodedImage;
+use crate::color::RenderColor;
+use crate::framebuffer::Framebuffer;
+use crate::tonemap::ToneMapping;
+use std::io::{self, Write};
+
+const MAX_COLOR: u32 = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PpmEncoding {
+    // "P3", one line of decimal values per pixel
+    Ascii,
+    // "P6", three bytes per pixel
+    Binary,
+}
+
+// Pixels are converted and written a row at a time, so `out` should be
+// buffered.
+pub fn write_ppm(
+    out: &mut dyn Write,
+    framebuffer: &Framebuffer,
+    tone_mapping: &ToneMapping,
+    encoding: PpmEncoding,
+) -> io::Result<()> {
+    let magic = match encoding {
+        PpmEncoding::Ascii => "P3",
+        PpmEncoding::Binary => "P6",
+    };
+    writeln!(out, "{}", magic)?;
+    writeln!(out, "{} {}", framebuffer.width(), framebuffer.height())?;
+    writeln!(out, "{}", MAX_COLOR)?;
+    let mut row_bytes = Vec::with_capacity(framebuffer.width() as usize * 3);
+    for row in framebuffer.rows() {
+        match encoding {
+            PpmEncoding::Ascii => {
+                for pixel in row {
+                    writeln!(out, "{}", RenderColor::from_color(tone_mapping.map(*pixel)))?;
+                }
+            }
+            PpmEncoding::Binary => {
+                row_bytes.clear();
+                for pixel in row {
+                    row_bytes.extend_from_slice(
+                        &RenderColor::from_color(tone_mapping.map(*pixel)).rgb(),
+                    );
+                }
+                out.write_all(&row_bytes)?;
+            }
+        }
+    }
+    out.flush()
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+// Reads the next whitespace separated header field, skipping comments.
+fn header_field<'a>(bytes: &'a [u8], position: &mut usize) -> io::Result<&'a [u8]> {
+    loop {
+        match bytes.get(*position) {
+            Some(b'#') => {
+                while bytes.get(*position).is_some_and(|byte| *byte != b'\n') {
+                    *position += 1;
+                }
+            }
+            Some(byte) if byte.is_ascii_whitespace() => *position += 1,
+            Some(_) => break,
+            None => return Err(invalid_data("unexpected end of file")),
+        }
+    }
+    let start = *position;
+    while bytes
+        .get(*position)
+        .is_some_and(|byte| !byte.is_ascii_whitespace())
+    {
+        *position += 1;
+    }
+    Ok(&bytes[start..*position])
+}
+
+fn header_number(bytes: &[u8], position: &mut usize) -> io::Result<u32> {
+    let field = header_field(bytes, position)?;
+    std::str::from_utf8(field)
+        .ok()
+        .and_then(|field| field.parse().ok())
+        .ok_or_else(|| invalid_data("invalid number in PPM header"))
+}
+
+// Reads either P3 or P6. P6 samples are two bytes, most significant first, if
+// the maximum value is over 255.
+pub fn read_ppm(bytes: &[u8]) -> io::Result<DecodedImage> {
+    let mut position = 0;
+    let encoding = match header_field(bytes, &mut position)? {
+        b"P3" => PpmEncoding::Ascii,
+        b"P6" => PpmEncoding::Binary,
+        _ => return Err(invalid_data("not a P3 or P6 PPM file")),
+    };
+    let width = header_number(bytes, &mut position)?;
+    let height = header_number(bytes, &mut position)?;
+    let max_value = header_number(bytes, &mut position)?;
+    if max_value == 0 || max_value > u16::MAX as u32 {
+        return Err(invalid_data("PPM maximum value must be from 1 to 65535"));
+    }
+    let over_maximum = || invalid_data("PPM sample is over the maximum value");
+    let truncated = || invalid_data("unexpected end of file");
+    // The header can't be trusted, so check the data is really there before
+    // allocating for it.
+    let sample_count = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixel_count| pixel_count.checked_mul(3))
+        .ok_or_else(truncated)?;
+    let mut samples = Vec::new();
+    match encoding {
+        PpmEncoding::Ascii => {
+            // each sample is at least a digit
+            if bytes.len().saturating_sub(position) < sample_count {
+                return Err(truncated());
+            }
+            samples.reserve_exact(sample_count);
+            for _ in 0..sample_count {
+                // compared before narrowing, so that large values aren't
+                // wrapped below the maximum
+                let sample = header_number(bytes, &mut position)?;
+                if sample > max_value {
+                    return Err(over_maximum());
+                }
+                samples.push(sample as u16);
+            }
+        }
+        PpmEncoding::Binary => {
+            // exactly one whitespace byte separates the header from the data
+            let data = &bytes[(position + 1).min(bytes.len())..];
+            let bytes_per_sample = if max_value > 255 { 2 } else { 1 };
+            if data.len() / bytes_per_sample < sample_count {
+                return Err(truncated());
+            }
+            samples.reserve_exact(sample_count);
+            samples.extend(
+                data.chunks_exact(bytes_per_sample).take(sample_count).map(
+                    |sample| match *sample {
+                        [high, low] => u16::from_be_bytes([high, low]),
+                        [value] => value as u16,
+                        _ => unreachable!(),
+                    },
+                ),
+            );
+        }
+    }
+    if samples.iter().any(|sample| *sample as u32 > max_value) {
+        return Err(over_maximum());
+    }
+    Ok(DecodedImage {
+        width,
+        height,
+        max_value: max_value as u16,
+        pixels: samples
+            .chunks_exact(3)
+            .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    fn test_framebuffer() -> Framebuffer {
+        Framebuffer::from_pixels(
+            2,
+            2,
+            &[
+                Color::new(1.0, 0.0, 0.25),
+                Color::new(0.0, 1.0, 0.0),
+                Color::new(0.0, 0.0, 1.0),
+                Color::new(0.0, 0.0, 0.0),
+            ],
+        )
+    }
+
+    fn write(encoding: PpmEncoding) -> Vec<u8> {
+        let mut bytes = vec![];
+        write_ppm(
+            &mut bytes,
+            &test_framebuffer(),
+            &ToneMapping::default(),
+            encoding,
+        )
+        .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn writes_ascii_ppm() {
+        assert_eq!(
+            String::from_utf8(write(PpmEncoding::Ascii)).unwrap(),
+            "P3\n2 2\n255\n255 0 137\n0 255 0\n0 0 255\n0 0 0\n"
+        );
+    }
+
+    #[test]
+    fn writes_binary_ppm() {
+        let bytes = write(PpmEncoding::Binary);
+        let header = b"P6\n2 2\n255\n";
+        assert_eq!(bytes[..header.len()], header[..]);
+        assert_eq!(
+            bytes[header.len()..],
+            [255, 0, 137, 0, 255, 0, 0, 0, 255, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn reads_back_both_encodings() {
+        for encoding in [PpmEncoding::Ascii, PpmEncoding::Binary] {
+            let image = read_ppm(&write(encoding)).unwrap();
+            assert_eq!((image.width, image.height, image.max_value), (2, 2, 255));
+            assert_eq!(
+                image.pixels,
+                [[255, 0, 137], [0, 255, 0], [0, 0, 255], [0, 0, 0]]
+            );
+        }
+    }
+
+    #[test]
+    fn reads_comments_and_16_bit_samples() {
+        let mut bytes = b"P6 # a comment\n1 1\n# another\n1000\n".to_vec();
+        bytes.extend_from_slice(&[0x03, 0xE8, 0x01, 0xF4, 0x00, 0x00]);
+        let image = read_ppm(&bytes).unwrap();
+        assert_eq!(image.max_value, 1000);
+        assert_eq!(image.pixels, [[1000, 500, 0]]);
+    }
+
+    #[test]
+    fn rejects_truncated_or_out_of_range_data() {
+        assert!(read_ppm(b"P6\n2 2\n255\n\x01\x02").is_err());
+        assert!(read_ppm(b"P3\n1 1\n10\n1 2 11\n").is_err());
+        assert!(read_ppm(b"P5\n1 1\n255\n\x00").is_err());
+    }
+
+    #[test]
+    fn rejects_huge_headers_without_allocating() {
+        for header in [
+            &b"P6\n100000 100000\n255\n"[..],
+            b"P3\n100000 100000\n255\n",
+            b"P6\n4294967295 4294967295\n65535\n",
+        ] {
+            let mut bytes = header.to_vec();
+            bytes.extend_from_slice(&[0; 12]);
+            assert_eq!(
+                read_ppm(&bytes).err().unwrap().to_string(),
+                "unexpected end of file"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_ascii_samples_too_large_for_16_bits() {
+        // 70000 would wrap to 4464, under the maximum
+        assert_eq!(
+            read_ppm(b"P3\n1 1\n65535\n70000 0 0\n")
+                .err()
+                .unwrap()
+                .to_string(),
+            "PPM sample is over the maximum value"
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use crate::color::Color;
+use crate::framebuffer::Framebuffer;
+use std::io::{self, Write};
+
+// Shared-exponent encoding: the mantissas of all three components are
+// scaled so the largest fits in a byte, with the exponent stored alongside.
+fn to_rgbe(color: Color) -> [u8; 4] {
+    let components = [color.r(), color.g(), color.b()].map(|component| component.max(0.0));
+    let largest = components[0].max(components[1]).max(components[2]);
+    if largest < 1e-32 || !largest.is_finite() {
+        return [0; 4];
+    }
+    // largest = mantissa * 2^exponent, with mantissa in [0.5, 1)
+    let mut exponent = largest.log2().floor() as i32 + 1;
+    if largest / 2f64.powi(exponent) >= 1.0 {
+        exponent += 1;
+    }
+    if exponent + 128 > 255 {
+        return [255, 255, 255, 255];
+    }
+    let scale = 256.0 / 2f64.powi(exponent);
+    let [r, g, b] = components.map(|component| (component * scale).min(255.0) as u8);
+    [r, g, b, (exponent + 128) as u8]
+}
+
+// Radiance RGBE ".hdr" image, without run-length encoding.
+pub fn write_hdr(out: &mut dyn Write, framebuffer: &Framebuffer) -> io::Result<()> {
+    write!(
+        out,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        framebuffer.height(),
+        framebuffer.width()
+    )?;
+    for pixel in framebuffer.pixels() {
+        out.write_all(&to_rgbe(*pixel))?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_rgbe(rgbe: [u8; 4]) -> [f64; 3] {
+        if rgbe[3] == 0 {
+            return [0.0; 3];
+        }
+        let scale = 2f64.powi(rgbe[3] as i32 - 128 - 8);
+        [rgbe[0], rgbe[1], rgbe[2]].map(|mantissa| (mantissa as f64 + 0.5) * scale)
+    }
+
+    #[test]
+    fn round_trips_within_precision() {
+        for color in [
+            Color::new(0.5, 0.25, 0.125),
+            Color::new(1.0, 1.0, 1.0),
+            Color::new(123.4, 5.6, 0.01),
+            Color::new(0.001, 0.002, 0.003),
+        ] {
+            let decoded = from_rgbe(to_rgbe(color));
+            let largest = color.r().max(color.g()).max(color.b());
+            for (decoded, original) in decoded.iter().zip([color.r(), color.g(), color.b()]) {
+                // precision is relative to the largest component
+                assert!(
+                    (decoded - original).abs() <= largest / 128.0,
+                    "{} vs {}",
+                    decoded,
+                    original
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn encodes_black_and_negative_as_zero() {
+        assert_eq!(to_rgbe(Color::black()), [0; 4]);
+        assert_eq!(to_rgbe(Color::new(-1.0, -2.0, 0.0)), [0; 4]);
+        assert_eq!(to_rgbe(Color::new(-1.0, 1.0, 0.0))[0], 0);
+    }
+
+    #[test]
+    fn writes_header_then_pixels() {
+        let pixels = [Color::new(1.0, 0.5, 0.0), Color::black()];
+        let mut bytes = vec![];
+        write_hdr(&mut bytes, &Framebuffer::from_pixels(2, 1, &pixels)).unwrap();
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n";
+        assert_eq!(bytes[..header.len()], header[..]);
+        assert_eq!(bytes[header.len()..], [128, 64, 0, 129, 0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,231 @@
+use crate::hittable::Hittable;
+use crate::vec3::Vec3;
+use std::f64::consts::PI;
+
+// An orthonormal basis with w along a given direction, for building
+// directions relative to e.g. a surface normal.
+#[derive(Debug, Clone, Copy)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn from_w(direction: Vec3) -> Self {
+        let w = direction.unit_vector();
+        // any vector not parallel to w will do for building the others
+        let helper = if w.0.abs() > 0.9 {
+            Vec3(0.0, 1.0, 0.0)
+        } else {
+            Vec3(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(helper).unit_vector();
+        let u = w.cross(v);
+        Self { u, v, w }
+    }
+
+    // Converts coordinates in this basis to world ones.
+    pub fn local(&self, coords: Vec3) -> Vec3 {
+        self.u * coords.0 + self.v * coords.1 + self.w * coords.2
+    }
+}
+
+// A probability density over directions, which can also be sampled from.
+pub trait Pdf {
+    // the density per steradian, for a direction of any length
+    fn value(&self, direction: Vec3) -> f64;
+    // A direction drawn from the density, made from a point evenly
+    // distributed over the unit square, e.g. from a Sampler.
+    fn generate(&self, sample: (f64, f64)) -> Vec3;
+}
+
+fn cosine_direction((u, v): (f64, f64)) -> Vec3 {
+    let phi = 2.0 * PI * u;
+    let radius = v.sqrt();
+    Vec3(phi.cos() * radius, phi.sin() * radius, (1.0 - v).sqrt())
+}
+
+// Directions in the hemisphere around a normal, more likely the closer they
+// are to it: the density is cos(theta) / pi, which is what a Lambertian
+// surface scatters with.
+pub struct CosinePdf {
+    basis: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(normal: Vec3) -> Self {
+        Self {
+            basis: Onb::from_w(normal),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let cosine = direction.unit_vector().dot(self.basis.w);
+        (cosine / PI).max(0.0)
+    }
+
+    fn generate(&self, sample: (f64, f64)) -> Vec3 {
+        self.basis.local(cosine_direction(sample))
+    }
+}
+
+// Every direction equally likely.
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self, (u, v): (f64, f64)) -> Vec3 {
+        let z = 1.0 - 2.0 * u;
+        let phi = 2.0 * PI * v;
+        let radius = (1.0 - z * z).sqrt();
+        Vec3(phi.cos() * radius, phi.sin() * radius, z)
+    }
+}
+
+// Directions from a point towards an object, e.g. a light, as given by the
+// object's own pdf_value and random.
+pub struct HittablePdf<'a> {
+    object: &'a dyn Hittable,
+    origin: Vec3,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(object: &'a dyn Hittable, origin: Vec3) -> Self {
+        Self { object, origin }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        self.object.pdf_value(self.origin, direction)
+    }
+
+    fn generate(&self, sample: (f64, f64)) -> Vec3 {
+        self.object.random(self.origin, sample)
+    }
+}
+
+// An even mix of two pdfs: directions are drawn from either with equal
+// chance, so the density is the average of theirs. Which one is picked by
+// which half of the square the sample is in, and the sample is then
+// stretched back over the whole square for that pdf.
+pub struct MixturePdf<'a> {
+    pdfs: [&'a dyn Pdf; 2],
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(first: &'a dyn Pdf, second: &'a dyn Pdf) -> Self {
+        Self {
+            pdfs: [first, second],
+        }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        0.5 * self.pdfs[0].value(direction) + 0.5 * self.pdfs[1].value(direction)
+    }
+
+    fn generate(&self, (u, v): (f64, f64)) -> Vec3 {
+        if u < 0.5 {
+            self.pdfs[0].generate((u * 2.0, v))
+        } else {
+            self.pdfs[1].generate((u * 2.0 - 1.0, v))
+        }
+    }
+}
+
+// Estimates the integral of a pdf over all directions, which should be 1, by
+// averaging it over uniformly sampled ones.
+#[cfg(test)]
+pub fn integrate(pdf: &dyn Pdf, sample_count: usize) -> f64 {
+    use crate::sampler::{IndependentSampler, Sampler};
+    let mut sampler = IndependentSampler::new(0);
+    let total: f64 = (0..sample_count)
+        .map(|_| pdf.value(SpherePdf.generate(sampler.get_2d())) * 4.0 * PI)
+        .sum();
+    total / sample_count as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::random;
+
+    const SAMPLE_COUNT: usize = 100_000;
+
+    #[test]
+    fn basis_is_orthonormal() {
+        for direction in [
+            Vec3(0.0, 0.0, 3.0),
+            Vec3(1.0, 0.0, 0.0),
+            Vec3(-0.3, 2.0, 0.5),
+            Vec3(0.0, -1.0, 0.0),
+        ] {
+            let basis = Onb::from_w(direction);
+            for axis in [basis.u, basis.v, basis.w] {
+                assert!((axis.length() - 1.0).abs() < 1e-9);
+            }
+            assert!(basis.u.dot(basis.v).abs() < 1e-9);
+            assert!(basis.v.dot(basis.w).abs() < 1e-9);
+            assert!(basis.w.dot(basis.u).abs() < 1e-9);
+            assert!((basis.w - direction.unit_vector()).length() < 1e-9);
+            assert!((basis.local(Vec3(0.0, 0.0, 1.0)) - basis.w).length() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn cosine_pdf_integrates_to_one() {
+        let pdf = CosinePdf::new(Vec3(0.2, 1.0, -0.4));
+        assert!((integrate(&pdf, SAMPLE_COUNT) - 1.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn cosine_pdf_generates_around_the_normal() {
+        let normal = Vec3(0.2, 1.0, -0.4).unit_vector();
+        let pdf = CosinePdf::new(normal);
+        let mut total_cosine = 0.0;
+        for _ in 0..SAMPLE_COUNT {
+            let direction = pdf.generate((random(), random()));
+            assert!((direction.length() - 1.0).abs() < 1e-9);
+            let cosine = direction.dot(normal);
+            assert!(cosine >= 0.0);
+            total_cosine += cosine;
+        }
+        // the mean of cos(theta) weighted by cos(theta) / pi is 2/3
+        assert!((total_cosine / SAMPLE_COUNT as f64 - 2.0 / 3.0).abs() < 0.01);
+        assert_eq!(pdf.value(-normal), 0.0);
+    }
+
+    #[test]
+    fn sphere_pdf_integrates_to_one() {
+        assert!((integrate(&SpherePdf, SAMPLE_COUNT) - 1.0).abs() < 1e-9);
+        let mean = (0..SAMPLE_COUNT).fold(Vec3(0.0, 0.0, 0.0), |sum, _| {
+            let direction = SpherePdf.generate((random(), random()));
+            assert!((direction.length() - 1.0).abs() < 1e-9);
+            sum + direction
+        }) / SAMPLE_COUNT as f64;
+        assert!(mean.length() < 0.02);
+    }
+
+    #[test]
+    fn mixture_averages_its_pdfs() {
+        let normal = Vec3(0.0, 0.0, 1.0);
+        let cosine = CosinePdf::new(normal);
+        let mixture = MixturePdf::new(&cosine, &SpherePdf);
+        assert!((integrate(&mixture, SAMPLE_COUNT) - 1.0).abs() < 0.02);
+        let expected = 0.5 / PI + 0.5 / (4.0 * PI);
+        assert!((mixture.value(normal) - expected).abs() < 1e-12);
+        // half the time the direction comes from the sphere, so may be below
+        let below = (0..SAMPLE_COUNT)
+            .filter(|_| mixture.generate((random(), random())).dot(normal) < 0.0)
+            .count();
+        assert!((below as f64 / SAMPLE_COUNT as f64 - 0.25).abs() < 0.01);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::vec3::Vec3;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+const POINT_COUNT: usize = 256;
+
+// Perlin gradient noise. Each lattice point gets a pseudo-random unit gradient
+// and the noise is interpolated between those of the surrounding cube, so it
+// is smooth everywhere and zero on the lattice. The same seed always gives the
+// same noise.
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+fn permutation(rng: &mut StdRng) -> Vec<usize> {
+    let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+    perm.shuffle(rng);
+    perm
+}
+
+// Hermite smoothing, so that the noise's gradient is continuous across cells.
+fn smooth(level: f64) -> f64 {
+    level * level * (3.0 - 2.0 * level)
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| loop {
+                let vec = Vec3(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                );
+                // picking from inside the sphere keeps the directions uniform
+                let length_squared = vec.length_squared();
+                if length_squared > 1e-6 && length_squared <= 1.0 {
+                    break vec.unit_vector();
+                }
+            })
+            .collect();
+        Self {
+            gradients,
+            perm_x: permutation(&mut rng),
+            perm_y: permutation(&mut rng),
+            perm_z: permutation(&mut rng),
+        }
+    }
+
+    // Roughly in -1 to 1.
+    pub fn noise(&self, point: Vec3) -> f64 {
+        let floor = Vec3(point.0.floor(), point.1.floor(), point.2.floor());
+        let offset = point - floor;
+        let (smooth_x, smooth_y, smooth_z) = (smooth(offset.0), smooth(offset.1), smooth(offset.2));
+        let (i, j, k) = (floor.0 as i64, floor.1 as i64, floor.2 as i64);
+        let lattice = |idx: i64| (idx & (POINT_COUNT as i64 - 1)) as usize;
+        let mut accumulated = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.gradients[self.perm_x[lattice(i + di)]
+                        ^ self.perm_y[lattice(j + dj)]
+                        ^ self.perm_z[lattice(k + dk)]];
+                    let corner = Vec3(di as f64, dj as f64, dk as f64);
+                    let weight =
+                        |corner: f64, level: f64| corner * level + (1.0 - corner) * (1.0 - level);
+                    accumulated += weight(corner.0, smooth_x)
+                        * weight(corner.1, smooth_y)
+                        * weight(corner.2, smooth_z)
+                        * gradient.dot(offset - corner);
+                }
+            }
+        }
+        accumulated
+    }
+
+    // Fractal noise: the sum of the magnitudes of several octaves of noise,
+    // each at twice the frequency and half the weight of the last. Never
+    // negative, and rarely over 1.
+    pub fn turbulence(&self, point: Vec3, octaves: u32) -> f64 {
+        let mut accumulated = 0.0;
+        let mut point = point;
+        let mut weight = 1.0;
+        for _ in 0..octaves {
+            accumulated += weight * self.noise(point).abs();
+            weight *= 0.5;
+            point *= 2.0;
+        }
+        accumulated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // points along a wandering line, for sampling the noise
+    fn sample_points(count: usize) -> impl Iterator<Item = Vec3> {
+        (0..count).map(|idx| {
+            let t = idx as f64 * 0.0371;
+            Vec3(t * 1.3 - 7.0, (t * 0.7).sin() * 5.0, t * -0.9 + 3.0)
+        })
+    }
+
+    #[test]
+    fn noise_is_in_range() {
+        let perlin = Perlin::new(0);
+        let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+        for point in sample_points(20_000) {
+            let noise = perlin.noise(point);
+            min = min.min(noise);
+            max = max.max(noise);
+        }
+        assert!(min >= -1.0 && max <= 1.0, "{} to {}", min, max);
+        // and actually varies
+        assert!(min < -0.3 && max > 0.3, "{} to {}", min, max);
+    }
+
+    #[test]
+    fn noise_is_zero_on_the_lattice() {
+        let perlin = Perlin::new(0);
+        for point in [
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(3.0, -2.0, 7.0),
+            Vec3(-300.0, 5.0, 1.0),
+        ] {
+            assert_eq!(perlin.noise(point), 0.0);
+        }
+    }
+
+    #[test]
+    fn noise_is_continuous() {
+        let perlin = Perlin::new(0);
+        let step = 1e-4;
+        for point in sample_points(5_000) {
+            // including across cell boundaries
+            for direction in [
+                Vec3(1.0, 0.0, 0.0),
+                Vec3(0.0, 1.0, 0.0),
+                Vec3(0.0, 0.0, 1.0),
+            ] {
+                let change = perlin.noise(point + direction * step) - perlin.noise(point);
+                assert!(change.abs() < 10.0 * step, "{} at {}", change, point);
+            }
+        }
+    }
+
+    #[test]
+    fn noise_depends_only_on_the_seed() {
+        let (a, b, other) = (Perlin::new(7), Perlin::new(7), Perlin::new(8));
+        let point = Vec3(0.3, 1.7, -2.4);
+        assert_eq!(a.noise(point), b.noise(point));
+        assert_ne!(a.noise(point), other.noise(point));
+    }
+
+    #[test]
+    fn turbulence_is_in_range() {
+        let perlin = Perlin::new(0);
+        for point in sample_points(5_000) {
+            let turbulence = perlin.turbulence(point, 7);
+            assert!((0.0..2.0).contains(&turbulence), "{}", turbulence);
+        }
+        assert_eq!(perlin.turbulence(Vec3(0.5, 0.5, 0.5), 0), 0.0);
+    }
+}
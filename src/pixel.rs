@@ -1,31 +1,111 @@
 use crate::color::Color;
 use crate::vec3::Vec3;
 
+// Errors are measured relative to at least this brightness, so that noise in
+// nearly black pixels, which can't be seen, doesn't count as a large error.
+const MIN_BRIGHTNESS: f64 = 0.01;
+
+// Relative luminance of a linear color, as in Rec. 709.
+fn luminance(color: Vec3) -> f64 {
+    0.2126 * color.0 + 0.7152 * color.1 + 0.0722 * color.2
+}
+
+// The mean color of a pixel's samples so far, and how much their brightness
+// varies, both updated with Welford's algorithm so that they stay accurate
+// however many samples there are.
+#[derive(Clone, Copy)]
 pub struct Pixel {
-    cumulative_color: Vec3,
+    mean: Vec3,
+    // the sum of the squared differences of the samples' luminance from the
+    // mean luminance
+    luminance_m2: f64,
     sample_count: u32,
 }
 
 impl Pixel {
     pub fn add_color(&mut self, color: Color) {
         self.sample_count += 1;
-        self.cumulative_color.0 += color.r();
-        self.cumulative_color.1 += color.g();
-        self.cumulative_color.2 += color.b();
+        let old_mean_luminance = luminance(self.mean);
+        self.mean += (color.vec - self.mean) / self.sample_count as f64;
+        let sample_luminance = luminance(color.vec);
+        self.luminance_m2 +=
+            (sample_luminance - old_mean_luminance) * (sample_luminance - luminance(self.mean));
     }
 
     pub fn get_color(&self) -> Color {
-        Color::new(
-            self.cumulative_color.0 / (self.sample_count as f64),
-            self.cumulative_color.1 / (self.sample_count as f64),
-            self.cumulative_color.2 / (self.sample_count as f64),
-        )
+        Color::new(self.mean.0, self.mean.1, self.mean.2)
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    // the sample variance of the samples' luminance
+    fn luminance_variance(&self) -> f64 {
+        self.luminance_m2 / (self.sample_count as f64 - 1.0)
+    }
+
+    // The standard error of the pixel's mean brightness, as a fraction of
+    // the brightness: roughly how visible the remaining noise is. Infinite
+    // until there are enough samples to tell.
+    pub fn relative_error(&self) -> f64 {
+        if self.sample_count < 2 {
+            return f64::INFINITY;
+        }
+        let standard_error = (self.luminance_variance() / self.sample_count as f64).sqrt();
+        standard_error / luminance(self.mean).max(MIN_BRIGHTNESS)
     }
 
     pub fn new() -> Self {
         Pixel {
+            mean: Vec3(0.0, 0.0, 0.0),
+            luminance_m2: 0.0,
             sample_count: 0,
-            cumulative_color: Vec3(0.0, 0.0, 0.0),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel_of(colors: &[Vec3]) -> Pixel {
+        let mut pixel = Pixel::new();
+        for color in colors {
+            pixel.add_color(Color { vec: *color });
+        }
+        pixel
+    }
+
+    #[test]
+    fn tracks_the_mean_and_variance() {
+        let greys = [0.2, 0.4, 0.9, 0.5];
+        let colors: Vec<Vec3> = greys.iter().map(|grey| Vec3(*grey, *grey, *grey)).collect();
+        let pixel = pixel_of(&colors);
+        assert_eq!(pixel.sample_count(), 4);
+        assert!((pixel.get_color().vec - Vec3(0.5, 0.5, 0.5)).length() < 1e-12);
+        // (0.09 + 0.01 + 0.16 + 0) / 3
+        assert!((pixel.luminance_variance() - 0.26 / 3.0).abs() < 1e-12);
+        let expected_error = (0.26 / 3.0 / 4.0_f64).sqrt() / 0.5;
+        assert!((pixel.relative_error() - expected_error).abs() < 1e-12);
+    }
+
+    #[test]
+    fn error_falls_as_samples_are_added() {
+        assert_eq!(pixel_of(&[]).relative_error(), f64::INFINITY);
+        assert_eq!(
+            pixel_of(&[Vec3(1.0, 0.0, 0.0)]).relative_error(),
+            f64::INFINITY
+        );
+        let alternating = |count: usize| {
+            let colors: Vec<Vec3> = (0..count)
+                .map(|idx| Vec3(1.0, 1.0, 1.0) * (idx % 2) as f64)
+                .collect();
+            pixel_of(&colors).relative_error()
+        };
+        assert!(alternating(100) < alternating(10) / 3.0);
+        // a flat pixel has no error at all, even in the dark
+        assert_eq!(pixel_of(&[Vec3(0.3, 0.1, 0.0); 5]).relative_error(), 0.0);
+        assert_eq!(pixel_of(&[Vec3(0.0, 0.0, 0.0); 5]).relative_error(), 0.0);
+    }
+}
@@ -0,0 +1,144 @@
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use std::sync::Arc;
+
+// An infinite plane through a point. It has no bounding box, so it can't go
+// in a BVH and is instead checked against every ray.
+pub struct Plane {
+    point: Vec3,
+    normal: Vec3,
+    // unit vectors along the plane, for the texture coordinates
+    tangent: Vec3,
+    bitangent: Vec3,
+    material: Arc<dyn Material + Send + Sync>,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, material: Arc<dyn Material + Send + Sync>) -> Self {
+        let normal = normal.unit_vector();
+        // any axis not parallel to the normal will do to build the tangent
+        let helper = if normal.0.abs() > 0.9 {
+            Vec3(0.0, 1.0, 0.0)
+        } else {
+            Vec3(1.0, 0.0, 0.0)
+        };
+        let tangent = helper.cross(normal).unit_vector();
+        let bitangent = normal.cross(tangent);
+        Self {
+            point,
+            normal,
+            tangent,
+            bitangent,
+            material,
+        }
+    }
+}
+
+// Where along a ray it crosses the plane through point with the given normal,
+// if that's between t_min and t_max. The normal needn't be a unit vector.
+pub fn plane_crossing(ray: Ray, point: Vec3, normal: Vec3, t_min: f64, t_max: f64) -> Option<f64> {
+    let t = (point - ray.origin).dot(normal) / ray.vector.dot(normal);
+    // rays parallel to the plane give an infinite or NaN t
+    if !t.is_finite() || t < t_min || t > t_max {
+        return None;
+    }
+    Some(t)
+}
+
+impl Hittable for Plane {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let t = plane_crossing(ray, self.point, self.normal, t_min, t_max)?;
+        let hit_point = ray.at(t);
+        let offset = hit_point - self.point;
+        let hit = Hit::new(self.normal, hit_point, ray, t, Arc::clone(&self.material));
+        // the texture repeats every unit along the plane
+        Some(hit.with_uv(
+            offset.dot(self.tangent).rem_euclid(1.0),
+            offset.dot(self.bitangent).rem_euclid(1.0),
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn ground() -> Plane {
+        Plane::new(
+            Vec3(0.0, -1.0, 0.0),
+            Vec3(0.0, 2.0, 0.0),
+            Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn hits_from_above_and_below() {
+        let plane = ground();
+        let hit = plane
+            .hit(
+                Ray::new(Vec3(100.0, 4.0, -7.0), Vec3(0.0, -1.0, 0.0)),
+                0.0,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert_eq!(hit.ray_t, 5.0);
+        assert_eq!(hit.hit_point, Vec3(100.0, -1.0, -7.0));
+        assert!(hit.front_face);
+        assert_eq!(hit.normal, Vec3(0.0, 1.0, 0.0));
+
+        let hit = plane
+            .hit(
+                Ray::new(Vec3(0.0, -3.0, 0.0), Vec3(1.0, 1.0, 0.0)),
+                0.0,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert_eq!(hit.ray_t, 2.0);
+        assert!(!hit.front_face);
+        assert_eq!(hit.normal, Vec3(0.0, -1.0, 0.0));
+    }
+
+    #[test]
+    fn misses_parallel_and_receding_rays() {
+        let plane = ground();
+        let parallel = Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, 1.0));
+        assert!(plane.hit(parallel, 0.0, f64::INFINITY).is_none());
+        let in_plane = Ray::new(Vec3(0.0, -1.0, 0.0), Vec3(1.0, 0.0, 0.0));
+        assert!(plane.hit(in_plane, 0.0, f64::INFINITY).is_none());
+        let receding = Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 1.0, 0.0));
+        assert!(plane.hit(receding, 0.0, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn uvs_repeat_along_the_plane() {
+        let plane = ground();
+        let down = Vec3(0.0, -1.0, 0.0);
+        for (x, z) in [(0.25, 0.75), (-3.5, 10.0), (1e6, -1e3)] {
+            let hit = plane
+                .hit(Ray::new(Vec3(x, 0.0, z), down), 0.0, f64::INFINITY)
+                .unwrap();
+            assert!((0.0..1.0).contains(&hit.u) && (0.0..1.0).contains(&hit.v));
+            let moved = plane
+                .hit(
+                    Ray::new(Vec3(x + 1.0, 0.0, z + 2.0), down),
+                    0.0,
+                    f64::INFINITY,
+                )
+                .unwrap();
+            assert!((moved.u - hit.u).abs() < 1e-6 && (moved.v - hit.v).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn is_unbounded() {
+        assert!(ground().bounding_box().is_none());
+    }
+}
@@ -4,11 +4,21 @@ use crate::vec3::Vec3;
 pub struct Ray {
     pub origin: Vec3,
     pub vector: Vec3,
+    // when the ray was sent, within the camera's shutter interval
+    pub time: f64,
 }
 
 impl Ray {
     pub fn new(origin: Vec3, vector: Vec3) -> Self {
-        Self { origin, vector }
+        Self {
+            origin,
+            vector,
+            time: 0.0,
+        }
+    }
+
+    pub fn with_time(self, time: f64) -> Self {
+        Self { time, ..self }
     }
 
     pub fn at(&self, t: f64) -> Vec3 {
@@ -0,0 +1,381 @@
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable};
+use crate::hittable_list::HittableList;
+use crate::material::Material;
+use crate::plane::plane_crossing;
+use crate::ray::Ray;
+use crate::utils::lerp;
+use crate::vec3::Vec3;
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RectPlane {
+    Xy,
+    Xz,
+    Yz,
+}
+
+impl RectPlane {
+    // The two axes the rectangle spans, then the axis it's perpendicular to.
+    fn axes(self) -> (usize, usize, usize) {
+        match self {
+            Self::Xy => (0, 1, 2),
+            Self::Xz => (0, 2, 1),
+            Self::Yz => (1, 2, 0),
+        }
+    }
+}
+
+fn point_on_axes(axes: (usize, usize, usize), a: f64, b: f64, k: f64) -> Vec3 {
+    let mut components = [0.0; 3];
+    components[axes.0] = a;
+    components[axes.1] = b;
+    components[axes.2] = k;
+    Vec3(components[0], components[1], components[2])
+}
+
+// A rectangle lying in an axis-aligned plane, e.g. an Xz rectangle covers
+// a_min.x..a_max.x and b_min.z..b_max.z at y = k. Its outward normal points
+// along the positive perpendicular axis, unless flipped.
+#[derive(Clone)]
+pub struct AxisRect {
+    plane: RectPlane,
+    a_min: f64,
+    a_max: f64,
+    b_min: f64,
+    b_max: f64,
+    k: f64,
+    flipped: bool,
+    material: Arc<dyn Material + Send + Sync>,
+}
+
+impl AxisRect {
+    pub fn new(
+        plane: RectPlane,
+        a: (f64, f64),
+        b: (f64, f64),
+        k: f64,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> Self {
+        Self {
+            plane,
+            a_min: a.0.min(a.1),
+            a_max: a.0.max(a.1),
+            b_min: b.0.min(b.1),
+            b_max: b.0.max(b.1),
+            k,
+            flipped: false,
+            material,
+        }
+    }
+
+    // The same rectangle facing the other way.
+    pub fn flipped(self) -> Self {
+        Self {
+            flipped: !self.flipped,
+            ..self
+        }
+    }
+
+    fn outwards_normal(&self) -> Vec3 {
+        let sign = if self.flipped { -1.0 } else { 1.0 };
+        point_on_axes(self.plane.axes(), 0.0, 0.0, sign)
+    }
+}
+
+impl Hittable for AxisRect {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let axes = self.plane.axes();
+        let (a_axis, b_axis, _) = axes;
+        let t = plane_crossing(
+            ray,
+            point_on_axes(axes, 0.0, 0.0, self.k),
+            point_on_axes(axes, 0.0, 0.0, 1.0),
+            t_min,
+            t_max,
+        )?;
+        let hit_point = ray.at(t);
+        let a = hit_point[a_axis];
+        let b = hit_point[b_axis];
+        if a < self.a_min || a > self.a_max || b < self.b_min || b > self.b_max {
+            return None;
+        }
+        let hit = Hit::new(
+            self.outwards_normal(),
+            hit_point,
+            ray,
+            t,
+            Arc::clone(&self.material),
+        );
+        Some(hit.with_uv(
+            (a - self.a_min) / (self.a_max - self.a_min),
+            (b - self.b_min) / (self.b_max - self.b_min),
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let axes = self.plane.axes();
+        Some(
+            Aabb::new(
+                point_on_axes(axes, self.a_min, self.b_min, self.k),
+                point_on_axes(axes, self.a_max, self.b_max, self.k),
+            )
+            // rectangles have no thickness along their normal
+            .padded(),
+        )
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        let hit = match self.hit(Ray::new(origin, direction), 0.001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => return 0.0,
+        };
+        // the rect's area as seen from origin shrinks with distance and as
+        // it turns away
+        let area = (self.a_max - self.a_min) * (self.b_max - self.b_min);
+        let distance_squared = hit.ray_t.powi(2) * direction.length_squared();
+        let cosine = (direction.dot(self.outwards_normal()) / direction.length()).abs();
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, origin: Vec3, (u, v): (f64, f64)) -> Vec3 {
+        let point = point_on_axes(
+            self.plane.axes(),
+            lerp(u, self.a_min, self.a_max),
+            lerp(v, self.b_min, self.b_max),
+            self.k,
+        );
+        point - origin
+    }
+}
+
+// An axis-aligned box made of six rectangles, all facing outwards.
+pub struct Cuboid {
+    min: Vec3,
+    max: Vec3,
+    sides: HittableList,
+}
+
+impl Cuboid {
+    pub fn new(
+        corner: Vec3,
+        opposite_corner: Vec3,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> Self {
+        let min = corner.min(opposite_corner);
+        let max = corner.max(opposite_corner);
+        let mut sides = HittableList::new();
+        for plane in [RectPlane::Xy, RectPlane::Xz, RectPlane::Yz] {
+            let (a_axis, b_axis, k_axis) = plane.axes();
+            let rect = |k| {
+                AxisRect::new(
+                    plane,
+                    (min[a_axis], max[a_axis]),
+                    (min[b_axis], max[b_axis]),
+                    k,
+                    Arc::clone(&material),
+                )
+            };
+            sides.add(rect(max[k_axis]));
+            sides.add(rect(min[k_axis]).flipped());
+        }
+        Self { min, max, sides }
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        self.sides.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.min, self.max))
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        self.sides.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: Vec3, sample: (f64, f64)) -> Vec3 {
+        self.sides.random(origin, sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::pdf::{integrate, HittablePdf, Pdf};
+    use rand::random;
+
+    fn material() -> Arc<dyn Material + Send + Sync> {
+        Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5)))
+    }
+
+    fn unit_rect(plane: RectPlane) -> AxisRect {
+        AxisRect::new(plane, (0.0, 1.0), (0.0, 2.0), 3.0, material())
+    }
+
+    #[test]
+    fn hits_xy_rect_from_both_sides() {
+        let rect = unit_rect(RectPlane::Xy);
+        let hit = rect
+            .hit(
+                Ray::new(Vec3(0.25, 0.5, 0.0), Vec3(0.0, 0.0, 1.0)),
+                0.0,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert_eq!(hit.ray_t, 3.0);
+        assert_eq!(hit.hit_point, Vec3(0.25, 0.5, 3.0));
+        // coming from behind the outward normal
+        assert!(!hit.front_face);
+        assert_eq!(hit.normal, Vec3(0.0, 0.0, -1.0));
+        assert_eq!((hit.u, hit.v), (0.25, 0.25));
+
+        let hit = rect
+            .hit(
+                Ray::new(Vec3(0.25, 0.5, 5.0), Vec3(0.0, 0.0, -1.0)),
+                0.0,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert!(hit.front_face);
+        assert_eq!(hit.normal, Vec3(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn normals_point_along_perpendicular_axis() {
+        let cases = [
+            (RectPlane::Xy, Vec3(0.0, 0.0, 1.0)),
+            (RectPlane::Xz, Vec3(0.0, 1.0, 0.0)),
+            (RectPlane::Yz, Vec3(1.0, 0.0, 0.0)),
+        ];
+        for (plane, normal) in cases {
+            let rect = unit_rect(plane);
+            assert_eq!(rect.outwards_normal(), normal);
+            assert_eq!(rect.flipped().outwards_normal(), -normal);
+            // shoot at the middle of the rect from the outward side
+            let (a_axis, b_axis, _) = plane.axes();
+            let mut target = [0.0; 3];
+            target[a_axis] = 0.5;
+            target[b_axis] = 1.0;
+            let target = Vec3(target[0], target[1], target[2]) + normal * 3.0;
+            let hit = unit_rect(plane)
+                .hit(Ray::new(target + normal, -normal), 0.0, f64::INFINITY)
+                .unwrap();
+            assert!(hit.front_face);
+            assert_eq!(hit.hit_point, target);
+            assert_eq!((hit.u, hit.v), (0.5, 0.5));
+        }
+    }
+
+    #[test]
+    fn edges_are_inclusive() {
+        let rect = unit_rect(RectPlane::Xz);
+        let down = Vec3(0.0, -1.0, 0.0);
+        for (x, z) in [(0.0, 0.0), (1.0, 2.0), (0.0, 2.0), (0.5, 0.0)] {
+            let hit = rect.hit(Ray::new(Vec3(x, 5.0, z), down), 0.0, f64::INFINITY);
+            assert!(hit.is_some(), "missed ({}, {})", x, z);
+        }
+        for (x, z) in [
+            (-1e-9, 1.0),
+            (1.0 + 1e-9, 1.0),
+            (0.5, -1e-9),
+            (0.5, 2.0 + 1e-9),
+        ] {
+            let hit = rect.hit(Ray::new(Vec3(x, 5.0, z), down), 0.0, f64::INFINITY);
+            assert!(hit.is_none(), "hit ({}, {})", x, z);
+        }
+    }
+
+    #[test]
+    fn misses_parallel_rays_and_respects_t_range() {
+        let rect = unit_rect(RectPlane::Yz);
+        let parallel = Ray::new(Vec3(3.0, 0.5, 0.5), Vec3(0.0, 1.0, 0.0));
+        assert!(rect.hit(parallel, 0.0, f64::INFINITY).is_none());
+        let ray = Ray::new(Vec3(0.0, 0.5, 0.5), Vec3(1.0, 0.0, 0.0));
+        assert!(rect.hit(ray, 0.0, 2.9).is_none());
+        assert!(rect.hit(ray, 3.1, f64::INFINITY).is_none());
+        assert!(rect.hit(ray, 0.0, 3.0).is_some());
+    }
+
+    #[test]
+    fn rect_bounding_box_is_padded() {
+        let bounding_box = unit_rect(RectPlane::Xz).bounding_box().unwrap();
+        assert_eq!(bounding_box.min.0, 0.0);
+        assert_eq!(bounding_box.max.2, 2.0);
+        assert!(bounding_box.min.1 < 3.0 && bounding_box.max.1 > 3.0);
+    }
+
+    #[test]
+    fn samples_directions_towards_the_rect() {
+        let rect = AxisRect::new(RectPlane::Xz, (-1.0, 1.0), (-1.0, 1.0), 1.0, material());
+        let origin = Vec3(0.3, 0.0, -0.2);
+        let pdf = HittablePdf::new(&rect, origin);
+        assert!((integrate(&pdf, 100_000) - 1.0).abs() < 0.02);
+        for _ in 0..1000 {
+            let direction = pdf.generate((random(), random()));
+            assert!(rect
+                .hit(Ray::new(origin, direction), 0.0, f64::INFINITY)
+                .is_some());
+            assert!(pdf.value(direction) > 0.0);
+        }
+        // straight up, the rect is 1 away and faces us
+        assert!((pdf.value(Vec3(0.0, 2.0, 0.0)) - 0.25).abs() < 1e-9);
+        assert_eq!(pdf.value(Vec3(0.0, -1.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn samples_directions_towards_the_box() {
+        let cuboid = Cuboid::new(Vec3(-1.0, 1.0, -1.0), Vec3(1.0, 2.0, 1.0), material());
+        let origin = Vec3(0.5, 0.0, 0.0);
+        let pdf = HittablePdf::new(&cuboid, origin);
+        assert!((integrate(&pdf, 100_000) - 1.0).abs() < 0.02);
+        for _ in 0..1000 {
+            let direction = pdf.generate((random(), random()));
+            assert!(cuboid
+                .hit(Ray::new(origin, direction), 0.0, f64::INFINITY)
+                .is_some());
+        }
+    }
+
+    #[test]
+    fn box_sides_face_outwards() {
+        let cuboid = Cuboid::new(Vec3(1.0, 1.0, 1.0), Vec3(-1.0, -2.0, -3.0), material());
+        let bounding_box = cuboid.bounding_box().unwrap();
+        assert_eq!(bounding_box.min, Vec3(-1.0, -2.0, -3.0));
+        assert_eq!(bounding_box.max, Vec3(1.0, 1.0, 1.0));
+        let directions = [
+            Vec3(1.0, 0.0, 0.0),
+            Vec3(-1.0, 0.0, 0.0),
+            Vec3(0.0, 1.0, 0.0),
+            Vec3(0.0, -1.0, 0.0),
+            Vec3(0.0, 0.0, 1.0),
+            Vec3(0.0, 0.0, -1.0),
+        ];
+        let center = Vec3(0.0, -0.5, -1.0);
+        for direction in directions {
+            // from outside, towards the center
+            let outside = cuboid
+                .hit(
+                    Ray::new(center + direction * 10.0, -direction),
+                    0.0,
+                    f64::INFINITY,
+                )
+                .unwrap();
+            assert!(outside.front_face);
+            assert_eq!(outside.normal, direction);
+            // from the center, outwards
+            let inside = cuboid
+                .hit(Ray::new(center, direction), 0.0, f64::INFINITY)
+                .unwrap();
+            assert!(!inside.front_face);
+            assert_eq!(inside.normal, -direction);
+        }
+        let miss = Ray::new(Vec3(5.0, 5.0, 5.0), Vec3(1.0, 0.0, 0.0));
+        assert!(cuboid.hit(miss, 0.0, f64::INFINITY).is_none());
+    }
+}
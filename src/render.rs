@@ -0,0 +1,593 @@
+use crate::bvh::BvhNode;
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::framebuffer::Framebuffer;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::pdf::{HittablePdf, MixturePdf, Pdf};
+use crate::pixel::Pixel;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene::{RenderSettings, Scene};
+use crate::tile::{spiral_tiles, Tile};
+use crate::vec3::Vec3;
+use std::{
+    ops::Range,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{channel, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    thread::JoinHandle,
+};
+
+const SHADOW_ACNE_AVOIDANCE_STEP: f64 = 0.001;
+const VERBOSE: bool = false;
+const DEFAULT_TILE_SIZE: u32 = 32;
+// Progressive passes double in length up to this many samples per pixel.
+const MAX_PASS_SAMPLES: u32 = 16;
+
+// Sent each time a thread finishes rendering a tile.
+#[derive(Debug, Clone, Copy)]
+pub struct TileProgress {
+    pub thread_idx: u32,
+    pub tile: Tile,
+    pub tiles_done: u32,
+    pub tile_count: u32,
+}
+
+// Everything rays are traced against.
+struct World {
+    objects: BvhNode,
+    // sampled directly when scattering off diffuse surfaces
+    lights: HittableList,
+}
+
+// The tiles still to be rendered, which each thread takes from until there
+// are none left, so that no thread sits idle while others have work queued.
+struct TileQueue {
+    tiles: Vec<Tile>,
+    next_idx: AtomicUsize,
+}
+
+impl TileQueue {
+    fn next(&self) -> Option<Tile> {
+        let idx = self.next_idx.fetch_add(1, Ordering::Relaxed);
+        self.tiles.get(idx).copied()
+    }
+}
+
+// Adds the given samples of the pixel to its accumulated color, stopping
+// early if adaptive sampling finds that it's already accurate enough.
+fn add_samples(
+    pixel: &mut Pixel,
+    (col, row): (u32, u32),
+    samples: Range<u32>,
+    camera: &Camera,
+    world: &World,
+    settings: &RenderSettings,
+    sampler: &mut dyn Sampler,
+) {
+    if VERBOSE {
+        eprintln!("ROW {} COL {}", row, col);
+    }
+    for i in samples {
+        if let Some(threshold) = settings.adaptive_threshold {
+            if pixel.sample_count() >= settings.min_samples_per_pixel
+                && pixel.relative_error() < threshold
+            {
+                break;
+            }
+        }
+        sampler.start_sample((col, row), i);
+        let (pixel_x, pixel_y) = sampler.get_2d();
+        let x_position = col as f64 + pixel_x;
+        let y_position = row as f64 + pixel_y;
+        let x_level = x_position / camera.image_width as f64;
+        let y_level = 1.0 - (y_position / camera.image_height as f64);
+        if VERBOSE {
+            eprintln!("SAMPLE {}, x {}, y {}", i, x_level, y_level);
+        }
+        let ray = camera.get_ray(x_level, y_level, sampler);
+        pixel.add_color(color_ray(
+            ray,
+            &world.objects,
+            &world.lights,
+            settings,
+            sampler,
+        ));
+    }
+}
+
+// The samples accumulated so far for every pixel of the image, row by row.
+// Each thread copies out the pixels of its tile, adds to them and copies them
+// back, so samples are added to each pixel in the same order however the
+// image is split up.
+struct Accumulators {
+    width: u32,
+    pixels: Mutex<Vec<Pixel>>,
+}
+
+impl Accumulators {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            pixels: Mutex::new(vec![Pixel::new(); width as usize * height as usize]),
+        }
+    }
+
+    fn index(&self, (col, row): (u32, u32)) -> usize {
+        row as usize * self.width as usize + col as usize
+    }
+
+    fn get_tile(&self, tile: &Tile, tile_pixels: &mut Vec<Pixel>) {
+        let pixels = self.pixels.lock().unwrap();
+        tile_pixels.clear();
+        tile_pixels.extend(tile.pixels().map(|position| pixels[self.index(position)]));
+    }
+
+    fn set_tile(&self, tile: &Tile, tile_pixels: &[Pixel]) {
+        let mut pixels = self.pixels.lock().unwrap();
+        for (position, pixel) in tile.pixels().zip(tile_pixels) {
+            pixels[self.index(position)] = *pixel;
+        }
+    }
+
+    fn framebuffer(&self) -> Framebuffer {
+        let pixels = self.pixels.lock().unwrap();
+        let colors: Vec<Color> = pixels.iter().map(Pixel::get_color).collect();
+        let height = (colors.len() / self.width.max(1) as usize) as u32;
+        Framebuffer::from_pixels(self.width, height, &colors)
+            .with_sample_counts(pixels.iter().map(Pixel::sample_count).collect())
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_thread(
+    thread_idx: u32,
+    queue: Arc<TileQueue>,
+    samples: Range<u32>,
+    camera: Camera,
+    world: Arc<World>,
+    settings: RenderSettings,
+    accumulators: Arc<Accumulators>,
+    tile_sender: Sender<(u32, Tile)>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut sampler = settings
+            .sampler
+            .sampler(settings.samples_per_pixel, settings.seed);
+        let mut tile_pixels = Vec::new();
+        while let Some(tile) = queue.next() {
+            accumulators.get_tile(&tile, &mut tile_pixels);
+            for (pixel, position) in tile_pixels.iter_mut().zip(tile.pixels()) {
+                add_samples(
+                    pixel,
+                    position,
+                    samples.clone(),
+                    &camera,
+                    &world,
+                    &settings,
+                    sampler.as_mut(),
+                );
+            }
+            accumulators.set_tile(&tile, &tile_pixels);
+            tile_sender.send((thread_idx, tile)).unwrap();
+        }
+    })
+}
+
+// How many samples per pixel each progressive pass adds: one, then as many
+// as have been taken so far, so that the image is updated often at first.
+fn pass_samples(samples_per_pixel: u32) -> Vec<Range<u32>> {
+    let mut passes = vec![];
+    let mut start = 0;
+    while start < samples_per_pixel {
+        let pass_length = start.clamp(1, MAX_PASS_SAMPLES);
+        let end = (start + pass_length).min(samples_per_pixel);
+        passes.push(start..end);
+        start = end;
+    }
+    passes
+}
+
+// Renders a scene's image in tiles shared out between threads. Neither the
+// number of threads nor the size of the tiles makes any difference to the
+// image.
+pub struct Renderer {
+    camera: Camera,
+    world: Arc<World>,
+    settings: RenderSettings,
+    thread_count: usize,
+    tile_size: u32,
+}
+
+impl Renderer {
+    // Builds the scene's bounding volume hierarchy, ready to render on as
+    // many threads as there are CPUs.
+    pub fn new(scene: &Scene) -> Self {
+        Self {
+            camera: scene.camera(),
+            world: Arc::new(World {
+                objects: BvhNode::new(&scene.world),
+                lights: scene.lights.clone(),
+            }),
+            settings: scene.settings,
+            thread_count: num_cpus::get(),
+            tile_size: DEFAULT_TILE_SIZE,
+        }
+    }
+
+    pub fn with_threads(self, thread_count: usize) -> Self {
+        Self {
+            thread_count: thread_count.max(1),
+            ..self
+        }
+    }
+
+    // Tiles are tile_size pixels square, 32 by default.
+    pub fn with_tile_size(self, tile_size: u32) -> Self {
+        Self {
+            tile_size: tile_size.max(1),
+            ..self
+        }
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn render(&self) -> Framebuffer {
+        self.render_with_progress(|_| {})
+    }
+
+    // Calls on_progress each time a tile is finished.
+    pub fn render_with_progress(&self, mut on_progress: impl FnMut(&TileProgress)) -> Framebuffer {
+        let accumulators = Arc::new(self.accumulators());
+        self.render_pass(
+            &accumulators,
+            0..self.settings.samples_per_pixel,
+            &mut on_progress,
+        );
+        accumulators.framebuffer()
+    }
+
+    // Renders the whole image at one sample per pixel, then adds more samples
+    // to every pixel in passes until there are samples_per_pixel of them,
+    // calling on_pass with the number of samples per pixel so far and the
+    // image so far after each pass. The final image is exactly the same as
+    // render gives.
+    pub fn render_progressive(&self, mut on_pass: impl FnMut(u32, &Framebuffer)) -> Framebuffer {
+        let accumulators = Arc::new(self.accumulators());
+        let mut framebuffer = accumulators.framebuffer();
+        for samples in pass_samples(self.settings.samples_per_pixel) {
+            let samples_done = samples.end;
+            self.render_pass(&accumulators, samples, &mut |_| {});
+            framebuffer = accumulators.framebuffer();
+            on_pass(samples_done, &framebuffer);
+        }
+        framebuffer
+    }
+
+    fn accumulators(&self) -> Accumulators {
+        Accumulators::new(self.camera.image_width, self.camera.image_height)
+    }
+
+    // Adds the given samples to every pixel.
+    fn render_pass(
+        &self,
+        accumulators: &Arc<Accumulators>,
+        samples: Range<u32>,
+        on_progress: &mut impl FnMut(&TileProgress),
+    ) {
+        let queue = Arc::new(TileQueue {
+            tiles: spiral_tiles(
+                self.camera.image_width,
+                self.camera.image_height,
+                self.tile_size,
+            ),
+            next_idx: AtomicUsize::new(0),
+        });
+        let tile_count = queue.tiles.len() as u32;
+        let (tile_sender, tile_receiver) = channel();
+        let handles: Vec<JoinHandle<()>> = (0..self.thread_count.min(tile_count as usize))
+            .map(|thread_idx| {
+                run_thread(
+                    thread_idx as u32,
+                    Arc::clone(&queue),
+                    samples.clone(),
+                    self.camera,
+                    Arc::clone(&self.world),
+                    self.settings,
+                    Arc::clone(accumulators),
+                    tile_sender.clone(),
+                )
+            })
+            .collect();
+        // so that the loop below ends when the threads do
+        drop(tile_sender);
+        for (tiles_done, (thread_idx, tile)) in tile_receiver.into_iter().enumerate() {
+            on_progress(&TileProgress {
+                thread_idx,
+                tile,
+                tiles_done: tiles_done as u32 + 1,
+                tile_count,
+            });
+        }
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+}
+
+// Follows a path from the camera, adding up the light found along it. The
+// throughput is how much of the light found at the current bounce makes it
+// back along the path to the camera.
+fn color_ray(
+    ray: Ray,
+    world: &dyn Hittable,
+    lights: &HittableList,
+    settings: &RenderSettings,
+    sampler: &mut dyn Sampler,
+) -> Color {
+    let mut color = Vec3(0.0, 0.0, 0.0);
+    let mut throughput = Vec3(1.0, 1.0, 1.0);
+    let mut ray = ray;
+    for bounce in 0..=settings.max_depth {
+        if VERBOSE {
+            eprintln!("coloring ray {:?}", ray);
+            eprintln!("bounce {}", bounce);
+        }
+        let hit = match world.hit(ray, SHADOW_ACNE_AVOIDANCE_STEP, f64::INFINITY) {
+            Some(hit) => hit,
+            None => {
+                let bg = settings.background.color(ray);
+                if VERBOSE {
+                    eprintln!("background: {:?}", bg);
+                }
+                color += throughput * bg.vec;
+                break;
+            }
+        };
+        if bounce == settings.max_depth {
+            if VERBOSE {
+                eprintln!("hit depth limit: black");
+            }
+            break;
+        }
+        let emitted = hit.material.emitted(&hit);
+        color += throughput * emitted.vec;
+        let scatter_result = match hit.material.scatter(&hit, sampler) {
+            Some(scatter_result) => scatter_result,
+            None => {
+                if VERBOSE {
+                    eprintln!("emitted: {:?}", emitted);
+                }
+                break;
+            }
+        };
+        // A ray drawn from the material's own pdf needs no weighting.
+        // Otherwise half the rays go towards the lights, and each is weighted
+        // by how likely the material was to scatter that way compared to how
+        // likely we were to pick it, which keeps the expected color the same.
+        let (scattered_ray, weight) = match &scatter_result.pdf {
+            Some(material_pdf) if !lights.objects().is_empty() => {
+                let light_pdf = HittablePdf::new(lights, hit.hit_point);
+                let mixture = MixturePdf::new(&light_pdf, material_pdf.as_ref());
+                let direction = mixture.generate(sampler.get_2d());
+                let scattering_pdf_value = material_pdf.value(direction);
+                if scattering_pdf_value <= 0.0 {
+                    // e.g. a light behind the surface
+                    break;
+                }
+                (
+                    Ray::new(hit.hit_point, direction).with_time(hit.ray.time),
+                    scattering_pdf_value / mixture.value(direction),
+                )
+            }
+            _ => (scatter_result.scattered_ray, 1.0),
+        };
+        if VERBOSE {
+            eprintln!("scattered");
+        }
+        throughput = throughput * scatter_result.material_color.vec * weight;
+        // Russian roulette: end the path with a chance that grows as its
+        // throughput falls, and make up for the paths ended by boosting the
+        // ones which survive, so the expected color is unchanged.
+        if bounce + 1 >= settings.roulette_depth {
+            let survival_chance = throughput.0.max(throughput.1).max(throughput.2).min(1.0);
+            if sampler.get_1d() >= survival_chance {
+                break;
+            }
+            throughput /= survival_chance;
+        }
+        ray = scattered_ray;
+    }
+    Color::from_vec(color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::Background;
+    use crate::material::{DiffuseLight, Lambertian, Metal};
+    use crate::plane::Plane;
+    use crate::rect::{AxisRect, RectPlane};
+    use crate::sampler::{IndependentSampler, SamplerKind};
+
+    const SAMPLE_COUNT: usize = 100_000;
+
+    // A grey floor lit by a small square light above it, and the mean and
+    // variance of the brightness seen looking straight down at the floor.
+    fn floor_brightness(sample_lights: bool, sampler_kind: SamplerKind) -> (f64, f64) {
+        let mut world = HittableList::new();
+        world.add(AxisRect::new(
+            RectPlane::Xz,
+            (-100.0, 100.0),
+            (-100.0, 100.0),
+            0.0,
+            Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+        ));
+        let light = AxisRect::new(
+            RectPlane::Xz,
+            (-0.5, 0.5),
+            (-0.5, 0.5),
+            1.0,
+            Arc::new(DiffuseLight::new(Vec3(4.0, 4.0, 4.0))),
+        )
+        .flipped();
+        world.add(light.clone());
+        let mut lights = HittableList::new();
+        if sample_lights {
+            lights.add(light);
+        }
+        let settings = RenderSettings {
+            max_depth: 5,
+            background: Background::Solid { color: [0.0; 3] },
+            ..RenderSettings::default()
+        };
+        let ray = Ray::new(Vec3(0.0, 0.5, 0.0), Vec3(0.0, -1.0, 0.0));
+        let mut sampler = sampler_kind.sampler(SAMPLE_COUNT as u32, 0);
+        mean_and_variance(|sample_idx| {
+            sampler.start_sample((0, 0), sample_idx);
+            color_ray(ray, &world, &lights, &settings, sampler.as_mut())
+                .vec
+                .0
+        })
+    }
+
+    fn mean_and_variance(mut sample: impl FnMut(u32) -> f64) -> (f64, f64) {
+        let samples: Vec<f64> = (0..SAMPLE_COUNT as u32).map(&mut sample).collect();
+        let mean = samples.iter().sum::<f64>() / SAMPLE_COUNT as f64;
+        let variance = samples
+            .iter()
+            .map(|sample| (sample - mean).powi(2))
+            .sum::<f64>()
+            / SAMPLE_COUNT as f64;
+        (mean, variance)
+    }
+
+    // A grey floor and ceiling under a white sky, so that light bounces
+    // between them many times before escaping out of the sides.
+    fn between_floor_and_ceiling(roulette_depth: u32) -> f64 {
+        let grey = Arc::new(Lambertian::new(Vec3(0.7, 0.7, 0.7)));
+        let mut world = HittableList::new();
+        for y in [0.0, 1.0] {
+            world.add(AxisRect::new(
+                RectPlane::Xz,
+                (-4.0, 4.0),
+                (-4.0, 4.0),
+                y,
+                grey.clone(),
+            ));
+        }
+        let settings = RenderSettings {
+            max_depth: 1000,
+            roulette_depth,
+            background: Background::Solid { color: [1.0; 3] },
+            ..RenderSettings::default()
+        };
+        let ray = Ray::new(Vec3(0.0, 0.5, 0.0), Vec3(0.3, -1.0, 0.1));
+        let mut sampler = IndependentSampler::new(0);
+        mean_and_variance(|_| {
+            color_ray(ray, &world, &HittableList::new(), &settings, &mut sampler)
+                .vec
+                .0
+        })
+        .0
+    }
+
+    #[test]
+    fn every_sampler_gives_the_same_color() {
+        let (expected, _) = floor_brightness(true, SamplerKind::Independent);
+        for kind in [
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let (mean, _) = floor_brightness(true, kind);
+            assert!(
+                (mean - expected).abs() < 0.02 * expected,
+                "{}: {} vs {}",
+                kind,
+                mean,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn russian_roulette_keeps_the_same_color() {
+        let without_roulette = between_floor_and_ceiling(1000);
+        let with_roulette = between_floor_and_ceiling(0);
+        assert!(
+            (with_roulette - without_roulette).abs() < 0.03 * without_roulette,
+            "{} vs {}",
+            with_roulette,
+            without_roulette
+        );
+    }
+
+    #[test]
+    fn deep_paths_dont_recurse() {
+        // a ray bouncing forever between two perfect mirrors, which roulette
+        // never ends as nothing is absorbed
+        let mirror = Arc::new(Metal::new(Vec3(1.0, 1.0, 1.0), 0.0));
+        let mut world = HittableList::new();
+        world.add(Plane::new(
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(0.0, 1.0, 0.0),
+            mirror.clone(),
+        ));
+        world.add(Plane::new(
+            Vec3(0.0, 1.0, 0.0),
+            Vec3(0.0, -1.0, 0.0),
+            mirror,
+        ));
+        let settings = RenderSettings {
+            max_depth: 1_000_000,
+            ..RenderSettings::default()
+        };
+        let ray = Ray::new(Vec3(0.0, 0.5, 0.0), Vec3(0.0, 1.0, 0.0));
+        let color = color_ray(
+            ray,
+            &world,
+            &HittableList::new(),
+            &settings,
+            &mut IndependentSampler::new(0),
+        );
+        assert_eq!(color.vec, Vec3(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn sampling_lights_gives_the_same_color_with_less_noise() {
+        let (unsampled_mean, unsampled_variance) =
+            floor_brightness(false, SamplerKind::Independent);
+        let (sampled_mean, sampled_variance) = floor_brightness(true, SamplerKind::Independent);
+        assert!(
+            (sampled_mean - unsampled_mean).abs() < 0.03 * unsampled_mean,
+            "{} vs {}",
+            sampled_mean,
+            unsampled_mean
+        );
+        assert!(
+            sampled_variance < unsampled_variance / 4.0,
+            "{} vs {}",
+            sampled_variance,
+            unsampled_variance
+        );
+    }
+
+    #[test]
+    fn progressive_passes_double_up_to_a_limit() {
+        assert_eq!(pass_samples(1), vec![0..1; 1]);
+        assert_eq!(pass_samples(6), [0..1, 1..2, 2..4, 4..6]);
+        let passes = pass_samples(100);
+        assert_eq!(passes[..6], [0..1, 1..2, 2..4, 4..8, 8..16, 16..32]);
+        assert_eq!(passes[6], 32..48);
+        assert_eq!(passes.last(), Some(&(96..100)));
+        assert!(pass_samples(0).is_empty());
+    }
+}
@@ -0,0 +1,561 @@
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+// Where the random numbers for each sample of a pixel come from: its position
+// within the pixel, on the lens, in time and the direction of each bounce.
+// Each call takes the next dimension of the sample, so samplers which spread
+// their samples out over the pixel can do so in every dimension. The numbers
+// depend only on the sampler's seed, the pixel, the sample and the dimension,
+// so renders can be reproduced exactly, however they're split over threads.
+pub trait Sampler {
+    // Starts a new sample, the sample_idx'th of its pixel.
+    fn start_sample(&mut self, pixel: (u32, u32), sample_idx: u32);
+    // in 0 to 1
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SamplerKind {
+    // every number independently random
+    Independent,
+    // jittered within a grid of strata over each pixel
+    Stratified,
+    // the Halton sequence, rotated differently for each pixel
+    Halton,
+    // a padded 2D Sobol sequence with hashed Owen scrambling
+    #[default]
+    Sobol,
+}
+
+impl SamplerKind {
+    pub const NAMES: [&'static str; 4] = ["independent", "stratified", "halton", "sobol"];
+
+    const ALL: [Self; 4] = [
+        Self::Independent,
+        Self::Stratified,
+        Self::Halton,
+        Self::Sobol,
+    ];
+
+    // Stratified samplers spread samples_per_pixel samples over each pixel.
+    pub fn sampler(self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            Self::Independent => Box::new(IndependentSampler::new(seed)),
+            Self::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            Self::Halton => Box::new(HaltonSampler::new(seed)),
+            Self::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+}
+
+impl fmt::Display for SamplerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let idx = Self::ALL.iter().position(|kind| kind == self).unwrap();
+        write!(f, "{}", Self::NAMES[idx])
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match Self::NAMES.iter().position(|known| *known == name) {
+            Some(idx) => Ok(Self::ALL[idx]),
+            None => Err(format!(
+                "unknown sampler '{}' (known samplers: {})",
+                name,
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+// The finaliser of MurmurHash3, which mixes the bits of its input well enough
+// to be used as random numbers.
+fn mix(mut value: u64) -> u64 {
+    value ^= value >> 33;
+    value = value.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    value ^= value >> 33;
+    value = value.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    value ^ (value >> 33)
+}
+
+pub fn hash(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(0, |hash, value| mix(hash ^ mix(value.wrapping_add(1))))
+}
+
+// A number in 0 to 1 from a hash.
+pub fn hash_to_level(hash: u64) -> f64 {
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn pixel_hash(seed: u64, pixel: (u32, u32)) -> u64 {
+    hash(&[seed, pixel.0 as u64, pixel.1 as u64])
+}
+
+// Element idx of a pseudo-random permutation of 0..length chosen by the seed,
+// without building the permutation. From Kensler's "Correlated Multi-Jittered
+// Sampling".
+fn permute(idx: u32, length: u32, seed: u32) -> u32 {
+    let mut mask = length - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    let mut idx = idx;
+    loop {
+        idx ^= seed;
+        idx = idx.wrapping_mul(0xe170_893d);
+        idx ^= seed >> 16;
+        idx ^= (idx & mask) >> 4;
+        idx ^= seed >> 8;
+        idx = idx.wrapping_mul(0x0929_eb3f);
+        idx ^= seed >> 23;
+        idx ^= (idx & mask) >> 1;
+        idx = idx.wrapping_mul(1 | seed >> 27);
+        idx = idx.wrapping_mul(0x6935_fa69);
+        idx ^= (idx & mask) >> 11;
+        idx = idx.wrapping_mul(0x74dc_b303);
+        idx ^= (idx & mask) >> 2;
+        idx = idx.wrapping_mul(0x9e50_1cc3);
+        idx ^= (idx & mask) >> 2;
+        idx = idx.wrapping_mul(0xc860_a3df);
+        idx &= mask;
+        idx ^= idx >> 5;
+        // the mask covers a power of two, so some results are out of range
+        if idx < length {
+            return ((idx as u64 + seed as u64) % length as u64) as u32;
+        }
+    }
+}
+
+// Every number is the hash of the pixel, sample and how many numbers have
+// been taken so far.
+pub struct IndependentSampler {
+    seed: u64,
+    sample_hash: u64,
+    count: u64,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            sample_hash: seed,
+            count: 0,
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, pixel: (u32, u32), sample_idx: u32) {
+        self.sample_hash = hash(&[pixel_hash(self.seed, pixel), sample_idx as u64]);
+        self.count = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.count += 1;
+        hash_to_level(hash(&[self.sample_hash, self.count]))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+// Splits each dimension of a pixel into as many strata as there are samples,
+// or in two dimensions into a square grid of as many as fit, and gives each
+// sample a random one of them, jittered. Which stratum goes with which sample
+// is shuffled independently for each dimension, so they don't line up.
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    seed: u64,
+    pixel_hash: u64,
+    sample_idx: u32,
+    dimension: u64,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        Self {
+            samples_per_pixel: samples_per_pixel.max(1),
+            seed,
+            pixel_hash: 0,
+            sample_idx: 0,
+            dimension: 0,
+        }
+    }
+
+    // The sample's stratum, out of samples_per_pixel, and a hash for jittering
+    // within it. Any samples beyond samples_per_pixel are stratified afresh in
+    // passes of that many.
+    fn next_stratum(&mut self) -> (u32, u64) {
+        let pass = self.sample_idx / self.samples_per_pixel;
+        let idx_in_pass = self.sample_idx % self.samples_per_pixel;
+        let seed = hash(&[self.pixel_hash, self.dimension, pass as u64]);
+        let jitter_hash = hash(&[seed, self.sample_idx as u64]);
+        self.dimension += 1;
+        (
+            permute(idx_in_pass, self.samples_per_pixel, seed as u32),
+            jitter_hash,
+        )
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, pixel: (u32, u32), sample_idx: u32) {
+        self.pixel_hash = pixel_hash(self.seed, pixel);
+        self.sample_idx = sample_idx;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (stratum, jitter_hash) = self.next_stratum();
+        (stratum as f64 + hash_to_level(jitter_hash)) / self.samples_per_pixel as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (cell, jitter_hash) = self.next_stratum();
+        let jitter = (hash_to_level(jitter_hash), hash_to_level(mix(jitter_hash)));
+        let grid_size = (self.samples_per_pixel as f64).sqrt() as u32;
+        if cell >= grid_size * grid_size {
+            // the samples which don't fit in the grid go anywhere
+            return jitter;
+        }
+        (
+            ((cell % grid_size) as f64 + jitter.0) / grid_size as f64,
+            ((cell / grid_size) as f64 + jitter.1) / grid_size as f64,
+        )
+    }
+}
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+// The digits of idx in the given base, mirrored about the point.
+fn radical_inverse(base: u64, idx: u64) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut weight = inverse_base;
+    let mut result = 0.0;
+    let mut idx = idx;
+    while idx > 0 {
+        result += (idx % base) as f64 * weight;
+        idx /= base;
+        weight *= inverse_base;
+    }
+    result
+}
+
+// Dimension d of sample i is the radical inverse of i in the d'th prime base,
+// shifted (modulo 1) by a random amount for each pixel so that neighbouring
+// pixels don't share a pattern. Dimensions beyond the table of primes are
+// just random.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel_hash: u64,
+    sample_idx: u32,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel_hash: 0,
+            sample_idx: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, pixel: (u32, u32), sample_idx: u32) {
+        self.pixel_hash = pixel_hash(self.seed, pixel);
+        self.sample_idx = sample_idx;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        let rotation_hash = hash(&[self.pixel_hash, dimension as u64]);
+        match PRIMES.get(dimension) {
+            Some(base) => {
+                let value =
+                    radical_inverse(*base, self.sample_idx as u64) + hash_to_level(rotation_hash);
+                value - value.floor()
+            }
+            None => hash_to_level(hash(&[rotation_hash, self.sample_idx as u64])),
+        }
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+// Laine and Karras's hash, which like an Owen scramble only lets each bit be
+// changed by the bits below it. With the bits reversed, that becomes Owen
+// scrambling: a random flip of every branch of the binary tree of intervals,
+// which keeps the Sobol sequence's stratification. From Burley's "Practical
+// Hash-based Owen Scrambling".
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    let mut value = value.reverse_bits();
+    value = value.wrapping_add(seed);
+    value ^= value.wrapping_mul(0x6c50_b47c);
+    value ^= value.wrapping_mul(0xb82f_1e52);
+    value ^= value.wrapping_mul(0xc7af_e638);
+    value ^= value.wrapping_mul(0x8d22_f6e6);
+    value.reverse_bits()
+}
+
+// The first two dimensions of the Sobol sequence.
+fn sobol_2d(idx: u32) -> (u32, u32) {
+    let mut y = 0;
+    let mut direction: u32 = 1 << 31;
+    let mut remaining = idx;
+    while remaining != 0 {
+        if remaining & 1 != 0 {
+            y ^= direction;
+        }
+        remaining >>= 1;
+        direction ^= direction >> 1;
+    }
+    (idx.reverse_bits(), y)
+}
+
+fn u32_to_level(value: u32) -> f64 {
+    value as f64 / (1u64 << 32) as f64
+}
+
+// Each dimension (or pair of dimensions) is a 2D Sobol sequence, Owen
+// scrambled and shuffled with seeds from the pixel and dimension, so any
+// power of two samples of a pixel are well stratified in each pair of
+// dimensions without the dimensions being correlated with each other.
+pub struct SobolSampler {
+    seed: u64,
+    pixel_hash: u64,
+    sample_idx: u32,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel_hash: 0,
+            sample_idx: 0,
+            dimension: 0,
+        }
+    }
+
+    fn next_point(&mut self) -> (f64, f64) {
+        let seed = hash(&[self.pixel_hash, self.dimension]);
+        self.dimension += 1;
+        let idx = nested_uniform_scramble(self.sample_idx, seed as u32);
+        let (x, y) = sobol_2d(idx);
+        (
+            u32_to_level(nested_uniform_scramble(x, mix(seed) as u32)),
+            u32_to_level(nested_uniform_scramble(y, (mix(seed) >> 32) as u32)),
+        )
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, pixel: (u32, u32), sample_idx: u32) {
+        self.pixel_hash = pixel_hash(self.seed, pixel);
+        self.sample_idx = sample_idx;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.next_point().0
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        self.next_point()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points_2d(sampler: &mut dyn Sampler, pixel: (u32, u32), count: u32) -> Vec<(f64, f64)> {
+        (0..count)
+            .map(|sample_idx| {
+                sampler.start_sample(pixel, sample_idx);
+                // skip a dimension, so it's not only the first that's tested
+                sampler.get_1d();
+                sampler.get_2d()
+            })
+            .collect()
+    }
+
+    // whether each cell of a columns x rows grid has exactly one point in it
+    fn one_per_cell(points: &[(f64, f64)], columns: u32, rows: u32) -> bool {
+        let mut counts = vec![0; (columns * rows) as usize];
+        for (x, y) in points {
+            let column = (x * columns as f64) as u32;
+            let row = (y * rows as f64) as u32;
+            counts[(row * columns + column) as usize] += 1;
+        }
+        counts.iter().all(|count| *count == 1)
+    }
+
+    #[test]
+    fn permute_gives_permutations() {
+        for length in [1, 2, 3, 7, 16, 50, 100] {
+            for seed in [0, 1, 12345, 0xdead_beef] {
+                let mut elements: Vec<u32> =
+                    (0..length).map(|idx| permute(idx, length, seed)).collect();
+                elements.sort_unstable();
+                assert_eq!(elements, (0..length).collect::<Vec<u32>>());
+            }
+        }
+        // and different seeds give different ones
+        let first: Vec<u32> = (0..16).map(|idx| permute(idx, 16, 1)).collect();
+        let second: Vec<u32> = (0..16).map(|idx| permute(idx, 16, 2)).collect();
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn samples_are_in_range() {
+        for kind in SamplerKind::ALL {
+            let mut sampler = kind.sampler(10, 0);
+            for sample_idx in 0..100 {
+                sampler.start_sample((3, 4), sample_idx);
+                for _ in 0..50 {
+                    let value = sampler.get_1d();
+                    assert!((0.0..1.0).contains(&value), "{} from {}", value, kind);
+                    let (x, y) = sampler.get_2d();
+                    assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn samples_depend_only_on_the_seed_pixel_sample_and_dimension() {
+        for kind in SamplerKind::ALL {
+            let sample = |seed, pixel, sample_idx| {
+                let mut sampler = kind.sampler(16, seed);
+                // whatever came before
+                sampler.start_sample((9, 9), 9);
+                sampler.get_2d();
+                sampler.start_sample(pixel, sample_idx);
+                (sampler.get_1d(), sampler.get_1d())
+            };
+            let first = sample(0, (1, 2), 3);
+            assert_eq!(sample(0, (1, 2), 3), first, "{}", kind);
+            assert_ne!(first.0, first.1);
+            assert_ne!(sample(0, (2, 1), 3), first);
+            assert_ne!(sample(0, (1, 2), 4), first);
+            assert_ne!(sample(1, (1, 2), 3), first);
+        }
+    }
+
+    #[test]
+    fn stratified_sampler_puts_one_sample_in_each_stratum() {
+        let mut sampler = StratifiedSampler::new(16, 0);
+        for pixel in [(0, 0), (5, 9)] {
+            let points = points_2d(&mut sampler, pixel, 16);
+            assert!(one_per_cell(&points, 4, 4));
+            // each 1D dimension has one sample in each of the 16 strata
+            let values: Vec<(f64, f64)> = (0..16)
+                .map(|sample_idx| {
+                    sampler.start_sample(pixel, sample_idx);
+                    (sampler.get_1d(), 0.0)
+                })
+                .collect();
+            assert!(one_per_cell(&values, 16, 1));
+        }
+        // further samples are stratified again
+        let points: Vec<(f64, f64)> = points_2d(&mut sampler, (0, 0), 32).split_off(16);
+        assert!(one_per_cell(&points, 4, 4));
+    }
+
+    #[test]
+    fn halton_sampler_is_stratified_in_its_first_dimensions() {
+        let mut sampler = HaltonSampler::new(0);
+        let points: Vec<(f64, f64)> = (0..8)
+            .map(|sample_idx| {
+                sampler.start_sample((2, 3), sample_idx);
+                (sampler.get_1d(), 0.0)
+            })
+            .collect();
+        assert!(one_per_cell(&points, 8, 1));
+        // base 3 and 5 for the next pair
+        let points = points_2d(&mut sampler, (2, 3), 5);
+        let ys: Vec<(f64, f64)> = points.iter().map(|point| (point.1, 0.0)).collect();
+        assert!(one_per_cell(&ys, 5, 1));
+        assert_eq!(radical_inverse(2, 6), 0.375);
+        assert!((radical_inverse(3, 5) - (2.0 / 3.0 + 1.0 / 9.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn sobol_sampler_is_stratified_in_every_elementary_interval() {
+        let mut sampler = SobolSampler::new(0);
+        for pixel in [(0, 0), (7, 1)] {
+            let points = points_2d(&mut sampler, pixel, 64);
+            for (columns, rows) in [(64, 1), (32, 2), (8, 8), (2, 32), (1, 64)] {
+                assert!(one_per_cell(&points, columns, rows), "{}x{}", columns, rows);
+            }
+            // and so is the first half of them
+            assert!(one_per_cell(&points[..32], 4, 8));
+        }
+    }
+
+    #[test]
+    fn low_discrepancy_samplers_converge_faster() {
+        // the mean over many pixels of the error in estimating the integral
+        // of a smooth function, which is 1/4
+        let mean_error = |kind: SamplerKind| {
+            let mut sampler = kind.sampler(256, 0);
+            let mut total_error = 0.0;
+            for pixel in 0..100 {
+                let points = points_2d(sampler.as_mut(), (pixel, 0), 256);
+                let estimate = points.iter().map(|(x, y)| x * y).sum::<f64>() / points.len() as f64;
+                total_error += (estimate - 0.25).abs();
+            }
+            total_error / 100.0
+        };
+        let independent = mean_error(SamplerKind::Independent);
+        for kind in [
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let error = mean_error(kind);
+            assert!(
+                error < independent / 3.0,
+                "{} {} vs {}",
+                kind,
+                error,
+                independent
+            );
+        }
+    }
+
+    #[test]
+    fn parses_sampler_names() {
+        for kind in SamplerKind::ALL {
+            assert_eq!(kind.to_string().parse::<SamplerKind>(), Ok(kind));
+        }
+        assert_eq!(
+            "random".parse::<SamplerKind>(),
+            Err(
+                "unknown sampler 'random' (known samplers: independent, stratified, halton, sobol)"
+                    .to_string()
+            )
+        );
+    }
+}
@@ -0,0 +1,1391 @@
+use crate::background::Background;
+use crate::camera::{Camera, CameraSettings};
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::instance::Instance;
+use crate::mat4::Mat4;
+use crate::material::{Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal};
+use crate::medium::ConstantMedium;
+use crate::obj::load_obj;
+use crate::plane::Plane;
+use crate::rect::{AxisRect, Cuboid, RectPlane};
+use crate::sampler::SamplerKind;
+use crate::sphere::{MovingSphere, ObjectSphere};
+use crate::texture::{
+    Checker, ImageTexture, NoisePattern, NoiseTexture, SolidColor, Texture, WrapMode,
+};
+use crate::tonemap::ToneMapping;
+use crate::triangle::Triangle;
+use crate::validate;
+use crate::vec3::Vec3;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use toml::Spanned;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderSettings {
+    #[serde(deserialize_with = "validate::non_zero")]
+    pub image_width: u32,
+    #[serde(deserialize_with = "validate::non_zero")]
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    // Bounces after which paths may be ended at random by Russian roulette,
+    // more likely the less light they can still carry.
+    pub roulette_depth: u32,
+    pub background: Background,
+    // how the random numbers for each sample are chosen
+    pub sampler: SamplerKind,
+    // The same seed, scene and settings always give exactly the same image.
+    pub seed: u64,
+    // With a threshold, adaptive sampling stops sampling each pixel once
+    // the relative error of its brightness is below it, after at least
+    // min_samples_per_pixel samples. samples_per_pixel is then the most any
+    // pixel gets.
+    pub adaptive_threshold: Option<f64>,
+    pub min_samples_per_pixel: u32,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            image_width: 1000,
+            samples_per_pixel: 50,
+            max_depth: 50,
+            roulette_depth: 5,
+            background: Background::Sky,
+            sampler: SamplerKind::Sobol,
+            seed: 0,
+            adaptive_threshold: None,
+            min_samples_per_pixel: 16,
+        }
+    }
+}
+
+pub struct Scene {
+    pub world: HittableList,
+    // Objects which are also in the world, and which the renderer sends rays
+    // towards on purpose, rather than waiting for them to be hit by chance.
+    pub lights: HittableList,
+    pub camera_settings: CameraSettings,
+    pub settings: RenderSettings,
+    pub tone_mapping: ToneMapping,
+}
+
+#[derive(Debug)]
+pub struct SceneError {
+    pub file: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.file.display(), self.message)
+    }
+}
+
+impl Error for SceneError {}
+
+fn white_color() -> [f64; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+fn white() -> TextureDescription {
+    TextureDescription::Color(white_color())
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+fn default_octaves() -> u32 {
+    7
+}
+
+fn black() -> [f64; 3] {
+    [0.0, 0.0, 0.0]
+}
+
+fn default_view_up() -> [f64; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn to_vec3(array: [f64; 3]) -> Vec3 {
+    Vec3(array[0], array[1], array[2])
+}
+
+// A negative radius makes a hollow sphere, but a zero, infinite or NaN one
+// makes nothing that can be rendered.
+fn sphere_radius(radius: f64) -> Result<f64, &'static str> {
+    if radius != 0.0 && radius.is_finite() {
+        Ok(radius)
+    } else {
+        Err("sphere radius must be finite and not zero")
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDescription {
+    #[serde(deserialize_with = "validate::positive")]
+    aspect_ratio: f64,
+    #[serde(deserialize_with = "validate::field_of_view")]
+    vertical_fov: f64,
+    #[serde(deserialize_with = "validate::finite_point")]
+    look_from: [f64; 3],
+    #[serde(deserialize_with = "validate::finite_point")]
+    look_at: [f64; 3],
+    #[serde(default = "default_view_up")]
+    view_up: [f64; 3],
+    #[serde(default)]
+    lens_radius: f64,
+    // defaults to the distance from look_from to look_at
+    focus_dist: Option<f64>,
+    // the same as the default start and end times of moving spheres
+    #[serde(default)]
+    shutter_open: f64,
+    #[serde(default = "one")]
+    shutter_close: f64,
+}
+
+// A material's color, either a plain [r, g, b] or a texture table such as
+// { type = "image", path = "earth.png" }.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextureDescription {
+    Color([f64; 3]),
+    Pattern(PatternDescription),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum PatternDescription {
+    // cubes of the given size, alternating between two textures
+    Checker {
+        even: Box<TextureDescription>,
+        odd: Box<TextureDescription>,
+        #[serde(default = "one")]
+        size: f64,
+    },
+    // a PNG or PPM file, relative to the scene file
+    Image {
+        path: String,
+        #[serde(default)]
+        wrap: WrapMode,
+    },
+    // procedural patterns made from Perlin noise
+    Noise(NoiseDescription),
+    Turbulence(NoiseDescription),
+    Marble(NoiseDescription),
+    Wood(NoiseDescription),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NoiseDescription {
+    #[serde(default = "one")]
+    scale: f64,
+    #[serde(default = "default_octaves")]
+    octaves: u32,
+    // the same seed always gives the same pattern
+    #[serde(default)]
+    seed: u64,
+    // the colors at either end of the pattern
+    #[serde(default = "black")]
+    low: [f64; 3],
+    #[serde(default = "white_color")]
+    high: [f64; 3],
+}
+
+impl NoiseDescription {
+    fn texture(&self, pattern: NoisePattern) -> NoiseTexture {
+        NoiseTexture::new(
+            pattern,
+            self.scale,
+            self.octaves,
+            self.seed,
+            to_vec3(self.low),
+            to_vec3(self.high),
+        )
+    }
+}
+
+impl TextureDescription {
+    fn texture(&self, directory: &Path) -> Result<Arc<dyn Texture>, String> {
+        Ok(match self {
+            Self::Color(color) => Arc::new(SolidColor::new(to_vec3(*color))),
+            Self::Pattern(PatternDescription::Checker { even, odd, size }) => {
+                // a zero size would make every cell index overflow
+                if !(*size > 0.0 && size.is_finite()) {
+                    return Err("checker size must be a finite number greater than 0".to_string());
+                }
+                Arc::new(Checker::new(
+                    even.texture(directory)?,
+                    odd.texture(directory)?,
+                    *size,
+                ))
+            }
+            Self::Pattern(PatternDescription::Noise(noise)) => {
+                Arc::new(noise.texture(NoisePattern::Noise))
+            }
+            Self::Pattern(PatternDescription::Turbulence(noise)) => {
+                Arc::new(noise.texture(NoisePattern::Turbulence))
+            }
+            Self::Pattern(PatternDescription::Marble(noise)) => {
+                Arc::new(noise.texture(NoisePattern::Marble))
+            }
+            Self::Pattern(PatternDescription::Wood(noise)) => {
+                Arc::new(noise.texture(NoisePattern::Wood))
+            }
+            Self::Pattern(PatternDescription::Image { path, wrap }) => {
+                let path = directory.join(path);
+                let image = ImageTexture::load(&path, *wrap)
+                    .map_err(|err| format!("{}: {}", path.display(), err))?;
+                Arc::new(image)
+            }
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDescription {
+    Lambertian {
+        color: TextureDescription,
+    },
+    Metal {
+        color: TextureDescription,
+        #[serde(default)]
+        fuzz: f64,
+    },
+    Dielectric {
+        refractive_index: f64,
+        #[serde(default = "white")]
+        color: TextureDescription,
+    },
+    DiffuseLight {
+        color: TextureDescription,
+    },
+    // for the particles in a constant_medium
+    Isotropic {
+        color: TextureDescription,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScaleDescription {
+    Uniform(f64),
+    PerAxis([f64; 3]),
+}
+
+// One step of an object's transform, written as e.g. { rotate_y = 15.0 }.
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TransformStep {
+    Translate([f64; 3]),
+    Scale(ScaleDescription),
+    RotateX(f64),
+    RotateY(f64),
+    RotateZ(f64),
+    Rotate { axis: [f64; 3], degrees: f64 },
+    // row-major
+    Matrix([[f64; 4]; 4]),
+}
+
+impl TransformStep {
+    fn matrix(&self) -> Result<Mat4, &'static str> {
+        Ok(match self {
+            Self::Translate(offset) => Mat4::translation(to_vec3(*offset)),
+            Self::Scale(ScaleDescription::Uniform(factor)) => {
+                Mat4::scaling(Vec3(*factor, *factor, *factor))
+            }
+            Self::Scale(ScaleDescription::PerAxis(factors)) => Mat4::scaling(to_vec3(*factors)),
+            Self::RotateX(degrees) => Mat4::rotation(Vec3(1.0, 0.0, 0.0), *degrees),
+            Self::RotateY(degrees) => Mat4::rotation(Vec3(0.0, 1.0, 0.0), *degrees),
+            Self::RotateZ(degrees) => Mat4::rotation(Vec3(0.0, 0.0, 1.0), *degrees),
+            Self::Rotate { axis, degrees } => {
+                if to_vec3(*axis).is_near_zero() {
+                    return Err("rotation axis can't be zero");
+                }
+                Mat4::rotation(to_vec3(*axis), *degrees)
+            }
+            Self::Matrix(rows) => {
+                if rows[3] != [0.0, 0.0, 0.0, 1.0] {
+                    return Err(
+                        "transform matrix must be affine, with a bottom row of [0, 0, 0, 1]",
+                    );
+                }
+                Mat4(*rows)
+            }
+        })
+    }
+}
+
+// Every object can be given a list of transform steps, applied in order.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDescription {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformStep>,
+    },
+    // a sphere moving in a straight line while the shutter is open, for
+    // motion blur
+    MovingSphere {
+        start_center: [f64; 3],
+        end_center: [f64; 3],
+        radius: f64,
+        #[serde(default)]
+        start_time: f64,
+        #[serde(default = "one")]
+        end_time: f64,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformStep>,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformStep>,
+    },
+    // An axis-aligned rectangle in the given plane, e.g. for "xz" min and max
+    // are the (x, z) corners and offset is the y it sits at. It faces along
+    // the positive remaining axis, or the negative one if flipped.
+    Rect {
+        plane: RectPlane,
+        min: [f64; 2],
+        max: [f64; 2],
+        offset: f64,
+        #[serde(default)]
+        flip: bool,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformStep>,
+    },
+    // an axis-aligned box between two opposite corners
+    Box {
+        min: [f64; 3],
+        max: [f64; 3],
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformStep>,
+    },
+    // an infinite plane
+    Plane {
+        point: [f64; 3],
+        normal: [f64; 3],
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformStep>,
+    },
+    // A volume of fog or smoke filling the boundary shape. The material is
+    // that of its particles, usually isotropic.
+    ConstantMedium {
+        boundary: BoundaryDescription,
+        density: f64,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformStep>,
+    },
+    // a Wavefront OBJ file, relative to the scene file. The material is used
+    // for any faces which don't have one from an MTL file.
+    Mesh {
+        path: String,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformStep>,
+    },
+}
+
+// The shape of a constant_medium, which needs to be closed and convex.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BoundaryDescription {
+    Sphere { center: [f64; 3], radius: f64 },
+    Box { min: [f64; 3], max: [f64; 3] },
+}
+
+impl ObjectDescription {
+    fn material(&self) -> &str {
+        match self {
+            Self::Sphere { material, .. }
+            | Self::MovingSphere { material, .. }
+            | Self::Triangle { material, .. }
+            | Self::Rect { material, .. }
+            | Self::Box { material, .. }
+            | Self::Plane { material, .. }
+            | Self::ConstantMedium { material, .. }
+            | Self::Mesh { material, .. } => material,
+        }
+    }
+
+    fn transform(&self) -> &[TransformStep] {
+        match self {
+            Self::Sphere { transform, .. }
+            | Self::MovingSphere { transform, .. }
+            | Self::Triangle { transform, .. }
+            | Self::Rect { transform, .. }
+            | Self::Box { transform, .. }
+            | Self::Plane { transform, .. }
+            | Self::ConstantMedium { transform, .. }
+            | Self::Mesh { transform, .. } => transform,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDescription {
+    #[serde(default)]
+    render: RenderSettings,
+    #[serde(default)]
+    tone_mapping: ToneMapping,
+    camera: CameraDescription,
+    #[serde(default)]
+    materials: HashMap<String, Spanned<MaterialDescription>>,
+    #[serde(default)]
+    objects: Vec<Spanned<ObjectDescription>>,
+}
+
+fn random_vec(rng: &mut StdRng) -> Vec3 {
+    Vec3(rng.gen(), rng.gen(), rng.gen())
+}
+
+// 1-based line and column of a byte offset into the source
+fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |idx| idx + 1) + 1;
+    (line, column)
+}
+
+impl Scene {
+    pub fn load(path: &Path) -> Result<Self, SceneError> {
+        let source = fs::read_to_string(path).map_err(|err| SceneError {
+            file: path.to_path_buf(),
+            message: err.to_string(),
+        })?;
+        Self::parse(&source, path)
+    }
+
+    // The path is used for error messages, and for resolving the paths of
+    // meshes and images relative to the scene file.
+    fn parse(source: &str, path: &Path) -> Result<Self, SceneError> {
+        let error = |message: String| SceneError {
+            file: path.to_path_buf(),
+            message,
+        };
+        let description: SceneDescription =
+            toml::from_str(source).map_err(|err| error(err.to_string()))?;
+        // an error in the table starting at the given byte offset
+        let error_at = |offset: usize, message: &str| {
+            let (line, column) = line_and_column(source, offset);
+            error(format!("line {}, column {}: {}", line, column, message))
+        };
+
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let mut materials: HashMap<&str, Arc<dyn Material + Send + Sync>> = HashMap::new();
+        for (name, material) in &description.materials {
+            let texture = |color: &TextureDescription| {
+                color.texture(directory).map_err(|message| {
+                    error_at(
+                        material.span().start,
+                        &format!("material '{}': {}", name, message),
+                    )
+                })
+            };
+            let material: Arc<dyn Material + Send + Sync> = match material.get_ref() {
+                MaterialDescription::Lambertian { color } => {
+                    Arc::new(Lambertian::textured(texture(color)?))
+                }
+                MaterialDescription::Metal { color, fuzz } => {
+                    Arc::new(Metal::textured(texture(color)?, *fuzz))
+                }
+                MaterialDescription::Dielectric {
+                    refractive_index,
+                    color,
+                } => Arc::new(Dielectric::textured(texture(color)?, *refractive_index)),
+                MaterialDescription::DiffuseLight { color } => {
+                    Arc::new(DiffuseLight::textured(texture(color)?))
+                }
+                MaterialDescription::Isotropic { color } => {
+                    Arc::new(Isotropic::textured(texture(color)?))
+                }
+            };
+            materials.insert(name, material);
+        }
+
+        let mut world = HittableList::new();
+        let mut lights = HittableList::new();
+        let mut meshes: HashMap<(PathBuf, &str), Arc<dyn Hittable>> = HashMap::new();
+        for object in &description.objects {
+            let material_name = object.get_ref().material();
+            let material = match materials.get(material_name) {
+                Some(material) => Arc::clone(material),
+                None => {
+                    let mut known_names: Vec<&str> = materials.keys().copied().collect();
+                    known_names.sort_unstable();
+                    return Err(error_at(
+                        object.span().start,
+                        &format!(
+                            "unknown material '{}' (known materials: {})",
+                            material_name,
+                            known_names.join(", ")
+                        ),
+                    ));
+                }
+            };
+            let located_error = |message: &str| error_at(object.span().start, message);
+            let shape: Arc<dyn Hittable> = match object.get_ref() {
+                ObjectDescription::Sphere { center, radius, .. } => {
+                    let radius = sphere_radius(*radius).map_err(located_error)?;
+                    Arc::new(ObjectSphere::new(radius, to_vec3(*center), material))
+                }
+                ObjectDescription::MovingSphere {
+                    start_center,
+                    end_center,
+                    radius,
+                    start_time,
+                    end_time,
+                    ..
+                } => {
+                    let radius = sphere_radius(*radius).map_err(located_error)?;
+                    Arc::new(MovingSphere::new(
+                        radius,
+                        to_vec3(*start_center),
+                        to_vec3(*end_center),
+                        (*start_time, *end_time),
+                        material,
+                    ))
+                }
+                ObjectDescription::Triangle { vertices, .. } => {
+                    Arc::new(Triangle::new(vertices.map(to_vec3), material))
+                }
+                ObjectDescription::Rect {
+                    plane,
+                    min,
+                    max,
+                    offset,
+                    flip,
+                    ..
+                } => {
+                    let rect = AxisRect::new(
+                        *plane,
+                        (min[0], max[0]),
+                        (min[1], max[1]),
+                        *offset,
+                        material,
+                    );
+                    Arc::new(if *flip { rect.flipped() } else { rect })
+                }
+                ObjectDescription::Box { min, max, .. } => {
+                    Arc::new(Cuboid::new(to_vec3(*min), to_vec3(*max), material))
+                }
+                ObjectDescription::Plane { point, normal, .. } => {
+                    if to_vec3(*normal).is_near_zero() {
+                        return Err(located_error("plane normal can't be zero"));
+                    }
+                    Arc::new(Plane::new(to_vec3(*point), to_vec3(*normal), material))
+                }
+                ObjectDescription::ConstantMedium {
+                    boundary, density, ..
+                } => {
+                    if *density <= 0.0 || density.is_nan() {
+                        return Err(located_error("medium density must be positive"));
+                    }
+                    // the boundary's own material is never used
+                    let boundary: Arc<dyn Hittable> = match boundary {
+                        BoundaryDescription::Sphere { center, radius } => {
+                            let radius = sphere_radius(*radius).map_err(located_error)?;
+                            Arc::new(ObjectSphere::new(
+                                radius,
+                                to_vec3(*center),
+                                Arc::clone(&material),
+                            ))
+                        }
+                        BoundaryDescription::Box { min, max } => Arc::new(Cuboid::new(
+                            to_vec3(*min),
+                            to_vec3(*max),
+                            Arc::clone(&material),
+                        )),
+                    };
+                    Arc::new(ConstantMedium::new(boundary, *density, material))
+                }
+                ObjectDescription::Mesh {
+                    path: mesh_path, ..
+                } => {
+                    let mesh_path = directory.join(mesh_path);
+                    // Each mesh is only loaded once per material, and shared
+                    // between all the objects using it.
+                    match meshes.entry((mesh_path, material_name)) {
+                        Entry::Occupied(entry) => Arc::clone(entry.get()),
+                        Entry::Vacant(entry) => {
+                            let mesh = load_obj(&entry.key().0, material)
+                                .map_err(|err| error(err.to_string()))?;
+                            Arc::clone(entry.insert(Arc::new(mesh)))
+                        }
+                    }
+                }
+            };
+            let transform = object.get_ref().transform();
+            // Only the shapes which know how to sample themselves are sampled
+            // as lights. Others still light the scene, just more noisily.
+            let is_light = matches!(
+                description
+                    .materials
+                    .get(material_name)
+                    .map(Spanned::get_ref),
+                Some(MaterialDescription::DiffuseLight { .. })
+            );
+            let can_be_sampled = matches!(
+                object.get_ref(),
+                ObjectDescription::Sphere { .. }
+                    | ObjectDescription::Rect { .. }
+                    | ObjectDescription::Box { .. }
+            );
+            if transform.is_empty() {
+                if is_light && can_be_sampled {
+                    lights.add_shared(Arc::clone(&shape));
+                }
+                world.add_shared(shape);
+            } else {
+                let mut matrix = Mat4::identity();
+                for step in transform {
+                    matrix = step.matrix().map_err(located_error)? * matrix;
+                }
+                match Instance::new(shape, matrix) {
+                    Some(instance) => world.add(instance),
+                    None => return Err(located_error("transform can't be inverted")),
+                }
+            }
+        }
+
+        let camera = &description.camera;
+        let look_from = to_vec3(camera.look_from);
+        let look_at = to_vec3(camera.look_at);
+        let camera_settings = CameraSettings {
+            aspect_ratio: camera.aspect_ratio,
+            vertical_fov_degrees: camera.vertical_fov,
+            look_from,
+            look_at,
+            view_up: to_vec3(camera.view_up),
+            lens_radius: camera.lens_radius,
+            focus_dist: camera
+                .focus_dist
+                .unwrap_or_else(|| (look_from - look_at).length()),
+            shutter_open: camera.shutter_open,
+            shutter_close: camera.shutter_close,
+        };
+        Ok(Self {
+            world,
+            lights,
+            camera_settings,
+            settings: description.render,
+            tone_mapping: description.tone_mapping,
+        })
+    }
+
+    pub fn camera(&self) -> Camera {
+        self.camera_settings.camera(self.settings.image_width)
+    }
+
+    // The final scene from "Ray Tracing in One Weekend" - a field of small
+    // random spheres around three big ones. The same seed always gives the
+    // same scene.
+    pub fn random_spheres(settings: RenderSettings, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut world = HittableList::new();
+
+        for a in -11..11 {
+            for b in -11..11 {
+                let choose_material: f64 = rng.gen();
+                let center = Vec3(
+                    (a as f64) + 0.9 * rng.gen::<f64>(),
+                    0.2,
+                    (b as f64) + 0.9 * rng.gen::<f64>(),
+                );
+                if (center - Vec3(4.0, 0.2, 0.0)).length() > 0.9 {
+                    let sphere_obj = if choose_material < 0.8 {
+                        // diffuse
+                        let sphere_material =
+                            Lambertian::new(random_vec(&mut rng) * random_vec(&mut rng));
+                        ObjectSphere::new(0.2, center, Arc::new(sphere_material))
+                    } else if choose_material < 0.95 {
+                        // metal
+                        let albedo = random_vec(&mut rng).remap(&(0.0..1.0), &(0.5..1.0));
+                        let fuzz = rng.gen_range(0.0..0.5);
+                        let sphere_material = Metal::new(albedo, fuzz);
+                        ObjectSphere::new(0.2, center, Arc::new(sphere_material))
+                    } else {
+                        // glass
+                        let sphere_material = Dielectric::new(Vec3(1.0, 1.0, 1.0), 1.5);
+                        ObjectSphere::new(0.2, center, Arc::new(sphere_material))
+                    };
+                    world.add(sphere_obj);
+                }
+            }
+        }
+
+        let camera_settings = CameraSettings {
+            aspect_ratio: 3.0 / 2.0,
+            vertical_fov_degrees: 20.0,
+            look_from: Vec3(13.0, 2.0, 3.0),
+            look_at: Vec3(0.0, 0.0, 0.0),
+            view_up: Vec3(0.0, 1.0, 0.0),
+            lens_radius: 0.05,
+            focus_dist: 10.0,
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+        };
+
+        let ground_material = Lambertian::new(Vec3(0.5, 0.5, 0.5));
+
+        // ground
+        world.add(Plane::new(
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(0.0, 1.0, 0.0),
+            Arc::new(ground_material),
+        ));
+
+        let material_1 = Dielectric::new(Vec3(1.0, 1.0, 1.0), 1.5);
+
+        world.add(ObjectSphere::new(
+            1.0,
+            Vec3(0.0, 1.0, 0.0),
+            Arc::new(material_1),
+        ));
+
+        let material_2 = Lambertian::new(Vec3(0.4, 0.2, 0.1));
+
+        world.add(ObjectSphere::new(
+            1.0,
+            Vec3(-4.0, 1.0, 0.0),
+            Arc::new(material_2),
+        ));
+
+        let material_3 = Metal::new(Vec3(0.7, 0.6, 0.5), 0.0);
+
+        world.add(ObjectSphere::new(
+            1.0,
+            Vec3(4.0, 1.0, 0.0),
+            Arc::new(material_3),
+        ));
+
+        Self {
+            world,
+            lights: HittableList::new(),
+            camera_settings,
+            settings,
+            tone_mapping: ToneMapping::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+    use crate::sampler::IndependentSampler;
+    use crate::tonemap::ToneMapOperator;
+    use crate::utils::TempDir;
+
+    fn parse(source: &str) -> Result<Scene, SceneError> {
+        Scene::parse(source, Path::new("test.toml"))
+    }
+
+    fn parse_error(source: &str) -> String {
+        match parse(source) {
+            Ok(_) => panic!("expected an error"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    const CAMERA: &str = "[camera]\n\
+                          aspect_ratio = 1.5\n\
+                          vertical_fov = 20.0\n\
+                          look_from = [13.0, 2.0, 3.0]\n\
+                          look_at = [0.0, 0.0, 0.0]\n";
+
+    #[test]
+    fn example_scenes_load() {
+        let scenes_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        for entry in fs::read_dir(scenes_dir).unwrap() {
+            let path = entry.unwrap().path();
+            // skip the images etc. which scenes use
+            if path.extension() != Some("toml".as_ref()) {
+                continue;
+            }
+            if let Err(err) = Scene::load(&path) {
+                panic!("{}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn render_settings_default_when_omitted() {
+        let scene = parse(CAMERA).unwrap();
+        assert_eq!(scene.settings.image_width, 1000);
+        assert_eq!(scene.settings.samples_per_pixel, 50);
+        assert_eq!(scene.settings.max_depth, 50);
+        assert_eq!(scene.settings.roulette_depth, 5);
+        assert_eq!(scene.settings.sampler, SamplerKind::Sobol);
+        assert_eq!(scene.settings.seed, 0);
+        assert_eq!(scene.settings.adaptive_threshold, None);
+        assert_eq!(scene.settings.min_samples_per_pixel, 16);
+        assert_eq!(scene.camera().image_height, 666);
+    }
+
+    #[test]
+    fn reads_render_settings() {
+        let source = format!(
+            "[render]\nimage_width = 300\nsamples_per_pixel = 10\nroulette_depth = 2\nsampler = \"halton\"\nseed = 7\nadaptive_threshold = 0.05\nmin_samples_per_pixel = 4\n{}",
+            CAMERA
+        );
+        let scene = parse(&source).unwrap();
+        assert_eq!(scene.settings.image_width, 300);
+        assert_eq!(scene.settings.samples_per_pixel, 10);
+        assert_eq!(scene.settings.max_depth, 50);
+        assert_eq!(scene.settings.roulette_depth, 2);
+        assert_eq!(scene.settings.sampler, SamplerKind::Halton);
+        assert_eq!(scene.settings.seed, 7);
+        assert_eq!(scene.settings.adaptive_threshold, Some(0.05));
+        assert_eq!(scene.settings.min_samples_per_pixel, 4);
+        assert_eq!(scene.settings.background, Background::Sky);
+        assert_eq!(scene.camera().image_width, 300);
+    }
+
+    #[test]
+    fn reads_background() {
+        let source = format!(
+            "[render.background]\ntype = \"solid\"\ncolor = [0.0, 0.0, 0.1]\n{}",
+            CAMERA
+        );
+        assert_eq!(
+            parse(&source).unwrap().settings.background,
+            Background::Solid {
+                color: [0.0, 0.0, 0.1]
+            }
+        );
+    }
+
+    #[test]
+    fn reads_tone_mapping() {
+        assert_eq!(parse(CAMERA).unwrap().tone_mapping, ToneMapping::default());
+        let source = format!(
+            "[tone_mapping]
+operator = \"reinhard-extended\"
+exposure = -1.5
+white_point = 8.0
+{}",
+            CAMERA
+        );
+        assert_eq!(
+            parse(&source).unwrap().tone_mapping,
+            ToneMapping {
+                operator: ToneMapOperator::ReinhardExtended,
+                exposure: -1.5,
+                white_point: 8.0,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_unusable_tone_mapping() {
+        for white_point in ["0.0", "-2.0", "inf", "nan"] {
+            let source = format!("[tone_mapping]\nwhite_point = {}\n{}", white_point, CAMERA);
+            let message = parse_error(&source);
+            assert!(message.contains("line 2, column 15"), "{}", message);
+            assert!(
+                message.contains("must be a finite number greater than 0"),
+                "{}",
+                message
+            );
+        }
+        for exposure in ["inf", "-inf", "nan"] {
+            let source = format!("[tone_mapping]\nexposure = {}\n{}", exposure, CAMERA);
+            let message = parse_error(&source);
+            assert!(message.contains("line 2, column 12"), "{}", message);
+            assert!(message.contains("must be a finite number"), "{}", message);
+        }
+    }
+
+    #[test]
+    fn builds_objects_with_named_materials() {
+        let source = format!(
+            "{}\n\
+             [materials.red]\ntype = \"lambertian\"\ncolor = [0.8, 0.1, 0.1]\n\
+             [materials.glass]\ntype = \"dielectric\"\nrefractive_index = 1.5\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"red\"\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0.0, 3.0, 0.0]\nradius = 1.0\nmaterial = \"glass\"\n",
+            CAMERA
+        );
+        let scene = parse(&source).unwrap();
+        assert_eq!(scene.world.objects().len(), 2);
+    }
+
+    #[test]
+    fn builds_rects_boxes_and_planes() {
+        let source = format!(
+            "{}\n\
+             [materials.white]\ntype = \"lambertian\"\ncolor = [0.7, 0.7, 0.7]\n\
+             [[objects]]\ntype = \"rect\"\nplane = \"xz\"\nmin = [0.0, 0.0]\nmax = [5.0, 5.0]\noffset = 2.0\nflip = true\nmaterial = \"white\"\n\
+             [[objects]]\ntype = \"box\"\nmin = [0.0, 0.0, 0.0]\nmax = [1.0, 2.0, 3.0]\nmaterial = \"white\"\n\
+             [[objects]]\ntype = \"plane\"\npoint = [0.0, -1.0, 0.0]\nnormal = [0.0, 1.0, 0.0]\nmaterial = \"white\"\n",
+            CAMERA
+        );
+        let scene = parse(&source).unwrap();
+        let objects = scene.world.objects();
+        assert_eq!(objects.len(), 3);
+        // the flipped rect faces down
+        let ray = Ray::new(Vec3(1.0, 0.0, 1.0), Vec3(0.0, 1.0, 0.0));
+        assert!(objects[0].hit(ray, 0.0, f64::INFINITY).unwrap().front_face);
+        assert_eq!(objects[1].bounding_box().unwrap().max, Vec3(1.0, 2.0, 3.0));
+        assert!(objects[2].bounding_box().is_none());
+    }
+
+    #[test]
+    fn rejects_zero_plane_normal() {
+        let source = format!(
+            "{}\n\
+             [materials.white]\ntype = \"lambertian\"\ncolor = [0.7, 0.7, 0.7]\n\
+             [[objects]]\ntype = \"plane\"\npoint = [0.0, 0.0, 0.0]\nnormal = [0.0, 0.0, 0.0]\nmaterial = \"white\"\n",
+            CAMERA
+        );
+        assert_eq!(
+            parse_error(&source),
+            "test.toml: line 10, column 1: plane normal can't be zero"
+        );
+    }
+
+    const WHITE: &str = "[materials.white]\ntype = \"lambertian\"\ncolor = [0.7, 0.7, 0.7]\n";
+
+    #[test]
+    fn applies_transform_steps_in_order() {
+        let source = format!(
+            "{}\n{}\n\
+             [[objects]]\ntype = \"box\"\nmin = [0.0, 0.0, 0.0]\nmax = [1.0, 1.0, 1.0]\nmaterial = \"white\"\n\
+             transform = [{{ scale = [2.0, 1.0, 1.0] }}, {{ rotate_z = 90.0 }}, {{ translate = [10.0, 0.0, 0.0] }}]\n",
+            CAMERA, WHITE
+        );
+        let scene = parse(&source).unwrap();
+        // 2 wide, stood on end, then moved along x
+        let bounding_box = scene.world.objects()[0].bounding_box().unwrap();
+        assert!((bounding_box.min - Vec3(9.0, 0.0, 0.0)).length() < 1e-9);
+        assert!((bounding_box.max - Vec3(10.0, 2.0, 1.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn reads_every_kind_of_transform_step() {
+        let source = format!(
+            "{}\n{}\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"white\"\n\
+             transform = [{{ scale = 2.0 }}, {{ rotate_x = 10.0 }}, {{ rotate_y = 20.0 }}, {{ rotate = {{ axis = [1.0, 1.0, 0.0], degrees = 45.0 }} }},\n\
+             {{ matrix = [[1.0, 0.0, 0.0, 5.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]] }}]\n",
+            CAMERA, WHITE
+        );
+        let scene = parse(&source).unwrap();
+        let centroid = scene.world.objects()[0].bounding_box().unwrap().centroid();
+        assert!(
+            (centroid - Vec3(5.0, 0.0, 0.0)).length() < 1e-9,
+            "{}",
+            centroid
+        );
+    }
+
+    #[test]
+    fn rejects_singular_transform() {
+        let source = format!(
+            "{}\n{}\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"white\"\n\
+             transform = [{{ scale = [1.0, 0.0, 1.0] }}]\n",
+            CAMERA, WHITE
+        );
+        assert_eq!(
+            parse_error(&source),
+            "test.toml: line 11, column 1: transform can't be inverted"
+        );
+    }
+
+    #[test]
+    fn rejects_degenerate_transform_steps() {
+        for (step, message) in [
+            (
+                "{ rotate = { axis = [0.0, 0.0, 0.0], degrees = 30.0 } }",
+                "rotation axis can't be zero",
+            ),
+            (
+                "{ matrix = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.5, 0.0, 1.0]] }",
+                "transform matrix must be affine, with a bottom row of [0, 0, 0, 1]",
+            ),
+        ] {
+            let source = format!(
+                "{}\n{}\n\
+                 [[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"white\"\n\
+                 transform = [{}]\n",
+                CAMERA, WHITE, step
+            );
+            assert_eq!(
+                parse_error(&source),
+                format!("test.toml: line 11, column 1: {}", message)
+            );
+        }
+    }
+
+    #[test]
+    fn shares_meshes_between_objects() {
+        let directory = TempDir::new("scene-shared-mesh");
+        fs::write(
+            directory.path().join("tri.obj"),
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n",
+        )
+        .unwrap();
+        let mesh = "[[objects]]\ntype = \"mesh\"\npath = \"tri.obj\"\nmaterial = \"white\"\n";
+        let source = format!(
+            "{}\n{}\n{}{}transform = [{{ translate = [0.0, 0.0, -1.0] }}]\n",
+            CAMERA, WHITE, mesh, mesh
+        );
+        let scene = Scene::parse(&source, &directory.path().join("scene.toml")).unwrap();
+        let objects = scene.world.objects();
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[0].bounding_box().unwrap().max.0, 1.0);
+        assert!(objects[1].bounding_box().unwrap().max.2 < -0.9);
+    }
+
+    // The color a material gives to rays hitting the first object at the point
+    // nearest +z.
+    fn color_from_above(scene: &Scene, center: Vec3) -> Vec3 {
+        let ray = Ray::new(center + Vec3(0.0, 0.0, 5.0), Vec3(0.0, 0.0, -1.0));
+        let hit = scene.world.objects()[0]
+            .hit(ray, 0.0, f64::INFINITY)
+            .unwrap();
+        hit.material
+            .scatter(&hit, &mut IndependentSampler::new(0))
+            .unwrap()
+            .material_color
+            .vec
+    }
+
+    #[test]
+    fn reads_checker_texture() {
+        let source = format!(
+            "{}\n\
+             [materials.checked]\ntype = \"lambertian\"\n\
+             color = {{ type = \"checker\", even = [1.0, 0.0, 0.0], odd = {{ type = \"checker\", even = [0.0, 1.0, 0.0], odd = [0.0, 0.0, 1.0] }}, size = 0.5 }}\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0.25, 0.25, 0.0]\nradius = 0.25\nmaterial = \"checked\"\n",
+            CAMERA
+        );
+        let scene = parse(&source).unwrap();
+        assert_eq!(
+            color_from_above(&scene, Vec3(0.25, 0.25, 0.0)),
+            Vec3(1.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn rejects_degenerate_checker_sizes() {
+        for size in ["0.0", "-1.0", "nan", "inf"] {
+            let source = format!(
+                "{}\n\
+                 [materials.checked]\ntype = \"lambertian\"\n\
+                 color = {{ type = \"checker\", even = [1.0, 1.0, 1.0], odd = [0.0, 0.0, 0.0], size = {} }}\n",
+                CAMERA, size
+            );
+            assert_eq!(
+                parse_error(&source),
+                "test.toml: line 7, column 1: material 'checked': checker size must be a finite number greater than 0"
+            );
+        }
+    }
+
+    #[test]
+    fn reads_noise_textures() {
+        for pattern in ["noise", "turbulence", "marble", "wood"] {
+            let source = format!(
+                "{}\n\
+                 [materials.procedural]\ntype = \"lambertian\"\n\
+                 color = {{ type = \"{}\", scale = 4.0, octaves = 3, seed = 12, low = [0.5, 0.5, 0.5], high = [0.5, 0.5, 0.5] }}\n\
+                 [[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"procedural\"\n",
+                CAMERA, pattern
+            );
+            let scene = parse(&source).unwrap();
+            let color = color_from_above(&scene, Vec3(0.0, 0.0, 0.0));
+            assert!((color - Vec3(0.5, 0.5, 0.5)).length() < 1e-12, "{}", color);
+        }
+        let unknown_field = format!(
+            "{}\n\
+             [materials.marble]\ntype = \"lambertian\"\n\
+             color = {{ type = \"marble\", colour = [1.0, 1.0, 1.0] }}\n",
+            CAMERA
+        );
+        assert!(parse(&unknown_field).is_err());
+    }
+
+    #[test]
+    fn reads_image_texture_relative_to_scene() {
+        let directory = TempDir::new("scene-image-texture");
+        fs::write(directory.path().join("red.ppm"), "P3\n1 1\n255\n255 0 0\n").unwrap();
+        let source = format!(
+            "{}\n\
+             [materials.red]\ntype = \"metal\"\n\
+             color = {{ type = \"image\", path = \"red.ppm\", wrap = \"clamp\" }}\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"red\"\n",
+            CAMERA
+        );
+        let scene = Scene::parse(&source, &directory.path().join("scene.toml")).unwrap();
+        assert_eq!(
+            color_from_above(&scene, Vec3(0.0, 0.0, 0.0)),
+            Vec3(1.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn reports_missing_image() {
+        let source = format!(
+            "{}\n\
+             [materials.earth]\ntype = \"lambertian\"\n\
+             color = {{ type = \"image\", path = \"missing.png\" }}\n",
+            CAMERA
+        );
+        let message = parse_error(&source);
+        assert!(
+            message.starts_with("test.toml: line 7, column 1: material 'earth': missing.png: "),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn reads_moving_sphere_and_shutter() {
+        let source = format!(
+            "{}shutter_open = 0.25\nshutter_close = 0.5\n\n{}\n\
+             [[objects]]\ntype = \"moving_sphere\"\nstart_center = [0.0, 0.0, 0.0]\nend_center = [0.0, 2.0, 0.0]\n\
+             radius = 0.5\nend_time = 0.5\nmaterial = \"white\"\n",
+            CAMERA, WHITE
+        );
+        let scene = parse(&source).unwrap();
+        assert_eq!(scene.camera_settings.shutter_open, 0.25);
+        assert_eq!(scene.camera_settings.shutter_close, 0.5);
+        let sphere = &scene.world.objects()[0];
+        let bounding_box = sphere.bounding_box().unwrap();
+        assert_eq!(bounding_box.max, Vec3(0.5, 2.5, 0.5));
+        // halfway through its move at time 0.25
+        let ray = Ray::new(Vec3(0.0, 0.0, 5.0), Vec3(0.0, 0.0, -1.0));
+        assert!(sphere.hit(ray.with_time(0.0), 0.0, f64::INFINITY).is_some());
+        assert!(sphere.hit(ray.with_time(0.5), 0.0, f64::INFINITY).is_none());
+        let ray = Ray::new(Vec3(0.0, 1.0, 5.0), Vec3(0.0, 0.0, -1.0)).with_time(0.25);
+        assert_eq!(sphere.hit(ray, 0.0, f64::INFINITY).unwrap().ray_t, 4.5);
+    }
+
+    #[test]
+    fn shutter_defaults_to_the_default_moving_sphere_times() {
+        let scene = parse(CAMERA).unwrap();
+        assert_eq!(
+            (
+                scene.camera_settings.shutter_open,
+                scene.camera_settings.shutter_close
+            ),
+            (0.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn reads_constant_medium() {
+        let source = format!(
+            "{}\n\
+             [materials.fog]\ntype = \"isotropic\"\ncolor = [0.9, 0.9, 0.9]\n\
+             [[objects]]\ntype = \"constant_medium\"\n\
+             boundary = {{ type = \"box\", min = [-1.0, -1.0, -1.0], max = [1.0, 1.0, 1.0] }}\n\
+             density = 1e9\nmaterial = \"fog\"\n",
+            CAMERA
+        );
+        let scene = parse(&source).unwrap();
+        let medium = &scene.world.objects()[0];
+        assert_eq!(medium.bounding_box().unwrap().max, Vec3(1.0, 1.0, 1.0));
+        let ray = Ray::new(Vec3(0.0, 0.0, 5.0), Vec3(0.0, 0.0, -1.0));
+        let hit = medium.hit(ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.ray_t - 4.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn collects_untransformed_lights() {
+        let source = format!(
+            "{}\n{}\n\
+             [materials.light]\ntype = \"diffuse_light\"\ncolor = [4.0, 4.0, 4.0]\n\
+             [[objects]]\ntype = \"rect\"\nplane = \"xz\"\nmin = [0.0, 0.0]\nmax = [1.0, 1.0]\noffset = 2.0\nmaterial = \"light\"\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"white\"\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0.0, 5.0, 0.0]\nradius = 1.0\nmaterial = \"light\"\n\
+             [[objects]]\ntype = \"box\"\nmin = [0.0, 0.0, 0.0]\nmax = [1.0, 1.0, 1.0]\nmaterial = \"light\"\n\
+             transform = [{{ translate = [0.0, 3.0, 0.0] }}]\n",
+            CAMERA, WHITE
+        );
+        let scene = parse(&source).unwrap();
+        assert_eq!(scene.world.objects().len(), 4);
+        // the transformed box still lights the scene, but isn't sampled
+        let lights = scene.lights.objects();
+        assert_eq!(lights.len(), 2);
+        assert!(Arc::ptr_eq(&lights[0], &scene.world.objects()[0]));
+        assert!(Arc::ptr_eq(&lights[1], &scene.world.objects()[2]));
+    }
+
+    #[test]
+    fn rejects_degenerate_sphere_radii() {
+        let objects = [
+            "type = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = {}",
+            "type = \"moving_sphere\"\nstart_center = [0.0, 0.0, 0.0]\n\
+             end_center = [0.0, 1.0, 0.0]\nradius = {}",
+            "type = \"constant_medium\"\n\
+             boundary = { type = \"sphere\", center = [0.0, 0.0, 0.0], radius = {} }\n\
+             density = 1.0",
+        ];
+        for object in objects {
+            for radius in ["0.0", "nan", "inf"] {
+                let source = format!(
+                    "{}\n{}\n[[objects]]\n{}\nmaterial = \"white\"\n",
+                    CAMERA,
+                    WHITE,
+                    object.replace("{}", radius)
+                );
+                assert_eq!(
+                    parse_error(&source),
+                    "test.toml: line 11, column 1: sphere radius must be finite and not zero",
+                    "{}",
+                    source
+                );
+            }
+        }
+        // but a negative radius makes a hollow sphere
+        let source = format!(
+            "{}\n{}\n[[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\n\
+             radius = -1.0\nmaterial = \"white\"\n",
+            CAMERA, WHITE
+        );
+        assert_eq!(parse(&source).unwrap().world.objects().len(), 1);
+    }
+
+    #[test]
+    fn rejects_nonsense_render_and_camera_settings() {
+        let render = |setting: &str| format!("[render]\n{}\n{}", setting, CAMERA);
+        let camera = |from: &str, to: &str| CAMERA.replace(from, to);
+        for (source, location, message) in [
+            (
+                render("samples_per_pixel = 0"),
+                "line 2, column 21",
+                "must be greater than 0",
+            ),
+            (
+                render("image_width = 0"),
+                "line 2, column 15",
+                "must be greater than 0",
+            ),
+            (
+                camera("aspect_ratio = 1.5", "aspect_ratio = 0.0"),
+                "line 2, column 16",
+                "must be a finite number greater than 0",
+            ),
+            (
+                camera("aspect_ratio = 1.5", "aspect_ratio = nan"),
+                "line 2, column 16",
+                "must be a finite number greater than 0",
+            ),
+            (
+                camera("vertical_fov = 20.0", "vertical_fov = 180.0"),
+                "line 3, column 16",
+                "must be an angle between 0 and 180 degrees",
+            ),
+            (
+                camera("vertical_fov = 20.0", "vertical_fov = -5.0"),
+                "line 3, column 16",
+                "must be an angle between 0 and 180 degrees",
+            ),
+            (
+                camera(
+                    "look_from = [13.0, 2.0, 3.0]",
+                    "look_from = [13.0, nan, 3.0]",
+                ),
+                "line 4, column 13",
+                "must be three finite numbers",
+            ),
+            (
+                camera("look_at = [0.0, 0.0, 0.0]", "look_at = [0.0, 0.0, inf]"),
+                "line 5, column 11",
+                "must be three finite numbers",
+            ),
+        ] {
+            let error = parse_error(&source);
+            assert!(error.contains(location), "{}", error);
+            assert!(error.contains(message), "{}", error);
+        }
+    }
+
+    #[test]
+    fn rejects_medium_without_density() {
+        for density in ["0.0", "-1.0", "nan"] {
+            let source = format!(
+                "{}\n{}\n\
+                 [[objects]]\ntype = \"constant_medium\"\n\
+                 boundary = {{ type = \"sphere\", center = [0.0, 0.0, 0.0], radius = 1.0 }}\n\
+                 density = {}\nmaterial = \"white\"\n",
+                CAMERA, WHITE, density
+            );
+            assert_eq!(
+                parse_error(&source),
+                "test.toml: line 11, column 1: medium density must be positive",
+                "{}",
+                density
+            );
+        }
+    }
+
+    #[test]
+    fn reports_unknown_material_with_location() {
+        let source = format!(
+            "{}\n\
+             [materials.red]\ntype = \"lambertian\"\ncolor = [0.8, 0.1, 0.1]\n\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"blue\"\n",
+            CAMERA
+        );
+        assert_eq!(
+            parse_error(&source),
+            "test.toml: line 11, column 1: unknown material 'blue' (known materials: red)"
+        );
+    }
+
+    #[test]
+    fn reports_missing_field() {
+        let source = format!(
+            "{}\n[[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nmaterial = \"red\"\n",
+            CAMERA
+        );
+        let message = parse_error(&source);
+        assert!(message.contains("line 7"), "{}", message);
+        assert!(message.contains("missing field `radius`"), "{}", message);
+    }
+
+    #[test]
+    fn reports_unknown_material_type() {
+        let source = format!(
+            "{}\n[materials.odd]\ntype = \"plastic\"\ncolor = [0.8, 0.1, 0.1]\n",
+            CAMERA
+        );
+        let message = parse_error(&source);
+        assert!(message.contains("unknown variant `plastic`"), "{}", message);
+    }
+
+    #[test]
+    fn reports_missing_camera() {
+        let message = parse_error("[render]\nimage_width = 300\n");
+        assert!(message.contains("missing field `camera`"), "{}", message);
+    }
+}
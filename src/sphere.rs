@@ -1,7 +1,11 @@
-use crate::hittable::Hit;
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable};
 use crate::material::Material;
+use crate::pdf::{Onb, Pdf, SpherePdf};
 use crate::ray::Ray;
+use crate::utils::{clamp, lerp};
 use crate::vec3::Vec3;
+use std::f64::consts::PI;
 use std::sync::Arc;
 
 #[derive(Clone)]
@@ -17,51 +21,34 @@ pub struct ObjectSphere {
 }
 
 impl GeometricSphere {
+    // Dividing by the radius rather than normalizing turns the normal inwards
+    // for a negative radius, making a hollow sphere whose outside is its back
+    // face, e.g. an air bubble inside a glass ball.
     pub fn normal_at(&self, point: Vec3) -> Vec3 {
-        (point - self.center).unit_vector()
+        (point - self.center) / self.radius
     }
 
-    pub fn random_point_in(&self) -> Vec3 {
-        loop {
-            let vec = Vec3::random_from_range(&(-self.radius..self.radius));
-            if vec.length_squared() < self.radius.powi(2) {
-                return self.center + vec;
-            }
-        }
-    }
-
-    pub fn random_point_in_hemisphere(&self, normal: Vec3) -> Vec3 {
-        let mut point;
-        loop {
-            point = self.random_point_in();
-            if point.dot(normal) > 0.0 {
-                break;
-            }
-        }
-        point
-    }
-
-    pub fn unit() -> Self {
-        Self {
-            radius: 1.0,
-            center: Vec3(0.0, 0.0, 0.0),
-        }
+    // Longitude and latitude, both from 0 to 1. u goes around from -x through
+    // +z, and v from the bottom pole to the top one.
+    pub fn uv_at(&self, point: Vec3) -> (f64, f64) {
+        let direction = (point - self.center).unit_vector();
+        let theta = (-direction.1).clamp(-1.0, 1.0).acos();
+        let phi = (-direction.2).atan2(direction.0) + PI;
+        (phi / (2.0 * PI), theta / PI)
     }
-}
 
-impl ObjectSphere {
-    pub fn new(radius: f64, center: Vec3, material: Arc<dyn Material + Send + Sync>) -> Self {
-        Self {
-            geometry: GeometricSphere { radius, center },
-            material: material,
-        }
-    }
-    pub fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<Hit> {
-        let center_to_ray_origin = ray.origin - self.geometry.center;
+    fn hit(
+        &self,
+        ray: Ray,
+        t_min: f64,
+        t_max: f64,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> Option<Hit> {
+        let center_to_ray_origin = ray.origin - self.center;
         // a, b, c as in the quadratic formula
         let a = ray.vector.dot(ray.vector);
         let b = ray.vector.dot(center_to_ray_origin) * 2.0;
-        let c = center_to_ray_origin.dot(center_to_ray_origin) - self.geometry.radius.powi(2);
+        let c = center_to_ray_origin.dot(center_to_ray_origin) - self.radius.powi(2);
         let discriminant = b.powi(2) - 4.0 * a * c;
         if discriminant < 0.0 {
             return None;
@@ -81,12 +68,142 @@ impl ObjectSphere {
         }
 
         let hit_point = ray.at(root);
-        Some(Hit::new(
-            self.geometry.normal_at(hit_point),
-            hit_point,
-            ray,
-            root,
-            Arc::clone(&self.material),
+        let (u, v) = self.uv_at(hit_point);
+        Some(Hit::new(self.normal_at(hit_point), hit_point, ray, root, material).with_uv(u, v))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // a hollow sphere, with a negative radius, takes the same space
+        let radius = self.radius.abs();
+        let radius_vec = Vec3(radius, radius, radius);
+        Aabb::new(self.center - radius_vec, self.center + radius_vec)
+    }
+
+    // The cosine of the angle between the direction to the center and the
+    // edge of the sphere, as seen from a point. None if the point is inside.
+    fn cos_theta_max(&self, point: Vec3) -> Option<f64> {
+        let distance_squared = (self.center - point).length_squared();
+        if distance_squared <= self.radius.powi(2) {
+            return None;
+        }
+        Some((1.0 - self.radius.powi(2) / distance_squared).sqrt())
+    }
+}
+
+impl ObjectSphere {
+    pub fn new(radius: f64, center: Vec3, material: Arc<dyn Material + Send + Sync>) -> Self {
+        Self {
+            geometry: GeometricSphere { radius, center },
+            material,
+        }
+    }
+}
+
+impl Hittable for ObjectSphere {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        self.geometry
+            .hit(ray, t_min, t_max, Arc::clone(&self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.geometry.bounding_box())
+    }
+
+    // Seen from outside, the sphere covers a cone of directions, which are
+    // sampled evenly. From inside it covers every direction.
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        let cos_theta_max = match self.geometry.cos_theta_max(origin) {
+            Some(cos_theta_max) => cos_theta_max,
+            None => return SpherePdf.value(direction),
+        };
+        if self
+            .hit(Ray::new(origin, direction), 0.001, f64::INFINITY)
+            .is_none()
+        {
+            return 0.0;
+        }
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+
+    fn random(&self, origin: Vec3, (u, v): (f64, f64)) -> Vec3 {
+        let cos_theta_max = match self.geometry.cos_theta_max(origin) {
+            Some(cos_theta_max) => cos_theta_max,
+            None => return SpherePdf.generate((u, v)),
+        };
+        let z = 1.0 + u * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * v;
+        let radius = (1.0 - z * z).sqrt();
+        Onb::from_w(self.geometry.center - origin).local(Vec3(
+            phi.cos() * radius,
+            phi.sin() * radius,
+            z,
+        ))
+    }
+}
+
+// A sphere moving in a straight line between two centers, at a constant speed
+// between the start and end times. Outside those times it stays at the
+// nearest end.
+pub struct MovingSphere {
+    start: GeometricSphere,
+    end: GeometricSphere,
+    start_time: f64,
+    end_time: f64,
+    material: Arc<dyn Material + Send + Sync>,
+}
+
+impl MovingSphere {
+    // times are the start and end times
+    pub fn new(
+        radius: f64,
+        start_center: Vec3,
+        end_center: Vec3,
+        times: (f64, f64),
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> Self {
+        Self {
+            start: GeometricSphere {
+                radius,
+                center: start_center,
+            },
+            end: GeometricSphere {
+                radius,
+                center: end_center,
+            },
+            start_time: times.0,
+            end_time: times.1,
+            material,
+        }
+    }
+
+    fn at_time(&self, time: f64) -> GeometricSphere {
+        let level = if self.end_time > self.start_time {
+            clamp(
+                (time - self.start_time) / (self.end_time - self.start_time),
+                0.0,
+                1.0,
+            )
+        } else {
+            0.0
+        };
+        GeometricSphere {
+            radius: self.start.radius,
+            center: lerp(level, self.start.center, self.end.center),
+        }
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        self.at_time(ray.time)
+            .hit(ray, t_min, t_max, Arc::clone(&self.material))
+    }
+
+    // covers the whole of the motion
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::surrounding(
+            self.start.bounding_box(),
+            self.end.bounding_box(),
         ))
     }
 }
@@ -94,6 +211,19 @@ impl ObjectSphere {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::material::Lambertian;
+    use crate::pdf::{integrate, HittablePdf};
+    use rand::random;
+
+    fn moving_sphere() -> MovingSphere {
+        MovingSphere::new(
+            1.0,
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(4.0, 0.0, 0.0),
+            (1.0, 2.0),
+            Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+        )
+    }
 
     #[test]
     fn can_get_normal() {
@@ -104,4 +234,117 @@ mod tests {
         let point = Vec3(1.0, 0.0, 0.0);
         assert_eq!(sphere.normal_at(point), Vec3(1.0, 0.0, 0.0));
     }
+
+    #[test]
+    fn negative_radius_turns_the_sphere_inside_out() {
+        let material = Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5)));
+        let ray = Ray::new(Vec3(0.0, 0.0, 5.0), Vec3(0.0, 0.0, -1.0));
+        let solid = ObjectSphere::new(1.0, Vec3(0.0, 0.0, 0.0), material.clone());
+        let hollow = ObjectSphere::new(-1.0, Vec3(0.0, 0.0, 0.0), material);
+        let solid_hit = solid.hit(ray, 0.0, f64::INFINITY).unwrap();
+        let hollow_hit = hollow.hit(ray, 0.0, f64::INFINITY).unwrap();
+        assert_eq!(solid_hit.ray_t, hollow_hit.ray_t);
+        assert!(solid_hit.front_face);
+        assert!(!hollow_hit.front_face);
+        // the normal still faces the ray, and the texture isn't turned around
+        assert_eq!(hollow_hit.normal, Vec3(0.0, 0.0, 1.0));
+        assert_eq!((hollow_hit.u, hollow_hit.v), (solid_hit.u, solid_hit.v));
+    }
+
+    #[test]
+    fn gets_longitude_and_latitude_as_uv() {
+        let sphere = GeometricSphere {
+            radius: 2.0,
+            center: Vec3(1.0, 2.0, 3.0),
+        };
+        let uv = |direction: Vec3| sphere.uv_at(sphere.center + direction * 2.0);
+        assert_eq!(uv(Vec3(0.0, 0.0, 1.0)), (0.25, 0.5));
+        assert_eq!(uv(Vec3(1.0, 0.0, 0.0)), (0.5, 0.5));
+        assert_eq!(uv(Vec3(0.0, 0.0, -1.0)), (0.75, 0.5));
+        assert_eq!(uv(Vec3(0.0, 1.0, 0.0)).1, 1.0);
+        assert_eq!(uv(Vec3(0.0, -1.0, 0.0)).1, 0.0);
+    }
+
+    #[test]
+    fn hit_has_uv() {
+        let sphere = ObjectSphere::new(
+            1.0,
+            Vec3(0.0, 0.0, 0.0),
+            Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+        );
+        let ray = Ray::new(Vec3(0.0, 0.0, 5.0), Vec3(0.0, 0.0, -1.0));
+        let hit = sphere.hit(ray, 0.0, f64::INFINITY).unwrap();
+        assert_eq!((hit.u, hit.v), (0.25, 0.5));
+    }
+
+    #[test]
+    fn samples_directions_towards_the_sphere() {
+        let sphere = ObjectSphere::new(
+            1.0,
+            Vec3(0.0, 0.0, -2.0),
+            Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+        );
+        let origin = Vec3(0.0, 0.0, 0.0);
+        let pdf = HittablePdf::new(&sphere, origin);
+        assert!((integrate(&pdf, 100_000) - 1.0).abs() < 0.02);
+        for _ in 0..1000 {
+            let direction = pdf.generate((random(), random()));
+            assert!(sphere
+                .hit(Ray::new(origin, direction), 0.0, f64::INFINITY)
+                .is_some());
+        }
+        // a cone with a half angle of 30 degrees
+        let solid_angle = 2.0 * PI * (1.0 - (PI / 6.0).cos());
+        assert!((pdf.value(Vec3(0.0, 0.0, -1.0)) - 1.0 / solid_angle).abs() < 1e-9);
+        assert_eq!(pdf.value(Vec3(0.0, 1.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn samples_every_direction_from_inside_the_sphere() {
+        let sphere = ObjectSphere::new(
+            2.0,
+            Vec3(0.0, 0.0, 0.0),
+            Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+        );
+        let pdf = HittablePdf::new(&sphere, Vec3(0.5, 0.0, 0.0));
+        assert!((integrate(&pdf, 10_000) - 1.0).abs() < 1e-9);
+        let below = (0..10_000)
+            .filter(|_| pdf.generate((random(), random())).1 < 0.0)
+            .count();
+        assert!((below as f64 / 10_000.0 - 0.5).abs() < 0.03);
+    }
+
+    #[test]
+    fn moving_sphere_is_where_it_is_at_the_ray_time() {
+        let sphere = moving_sphere();
+        let down = |x: f64, time: f64| {
+            let ray = Ray::new(Vec3(x, 5.0, 0.0), Vec3(0.0, -1.0, 0.0)).with_time(time);
+            sphere.hit(ray, 0.0, f64::INFINITY).map(|hit| hit.hit_point)
+        };
+        assert_eq!(down(2.0, 1.5), Some(Vec3(2.0, 1.0, 0.0)));
+        assert!(down(2.0, 1.0).is_none());
+        assert_eq!(down(0.0, 1.0), Some(Vec3(0.0, 1.0, 0.0)));
+        // clamped outside the start and end times
+        assert_eq!(down(0.0, -3.0), Some(Vec3(0.0, 1.0, 0.0)));
+        assert_eq!(down(4.0, 7.0), Some(Vec3(4.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn moving_sphere_box_covers_the_whole_motion() {
+        let bounding_box = moving_sphere().bounding_box().unwrap();
+        assert_eq!(bounding_box.min, Vec3(-1.0, -1.0, -1.0));
+        assert_eq!(bounding_box.max, Vec3(5.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn moving_sphere_with_no_duration_stays_at_the_start() {
+        let sphere = MovingSphere::new(
+            1.0,
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(4.0, 0.0, 0.0),
+            (1.0, 1.0),
+            Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+        );
+        assert_eq!(sphere.at_time(1.0).center, Vec3(0.0, 0.0, 0.0));
+    }
 }
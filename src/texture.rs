@@ -0,0 +1,371 @@
+use crate::color::{srgb_decode, Color};
+use crate::output::{read_image, DecodedImage};
+use crate::perlin::Perlin;
+use crate::utils::lerp;
+use crate::vec3::Vec3;
+use serde::Deserialize;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+// Gives the color of a surface at a point, from either its surface
+// coordinates (u, v) or its position.
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, point: Vec3) -> Color;
+}
+
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color_vec: Vec3) -> Self {
+        Self {
+            color: Color::from_vec(color_vec),
+        }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _point: Vec3) -> Color {
+        self.color
+    }
+}
+
+// A 3D checkerboard of cubes with sides of the given size, alternating between
+// two textures. Being solid rather than painted on, it works on any surface
+// whether or not it has UVs.
+pub struct Checker {
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+    size: f64,
+}
+
+impl Checker {
+    pub fn new(even: Arc<dyn Texture>, odd: Arc<dyn Texture>, size: f64) -> Self {
+        Self { even, odd, size }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, point: Vec3) -> Color {
+        let cell = |coord: f64| (coord / self.size).floor() as i64;
+        if (cell(point.0) + cell(point.1) + cell(point.2)).rem_euclid(2) == 0 {
+            self.even.value(u, v, point)
+        } else {
+            self.odd.value(u, v, point)
+        }
+    }
+}
+
+// What an image texture does with UVs outside 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WrapMode {
+    // tile the image
+    #[default]
+    Repeat,
+    // stretch the edge pixels
+    Clamp,
+    // tile the image, flipping every other copy
+    Mirror,
+}
+
+impl WrapMode {
+    // Maps a pixel index which may be outside the image onto one inside it.
+    fn wrap(self, idx: i64, size: u32) -> usize {
+        let size = size as i64;
+        let wrapped = match self {
+            Self::Repeat => idx.rem_euclid(size),
+            Self::Clamp => idx.clamp(0, size - 1),
+            Self::Mirror => {
+                let idx = idx.rem_euclid(2 * size);
+                if idx < size {
+                    idx
+                } else {
+                    2 * size - 1 - idx
+                }
+            }
+        };
+        wrapped as usize
+    }
+}
+
+// An image stretched over the surface's UVs, with (0, 0) at the bottom left of
+// the image. Colors are interpolated between the four nearest pixels.
+pub struct ImageTexture {
+    width: u32,
+    height: u32,
+    // linear colors, from the top row down
+    pixels: Vec<Color>,
+    wrap: WrapMode,
+}
+
+impl ImageTexture {
+    pub fn new(image: &DecodedImage, wrap: WrapMode) -> Self {
+        let max_value = image.max_value as f64;
+        let decode = |sample: u16| srgb_decode(sample as f64 / max_value);
+        Self {
+            width: image.width,
+            height: image.height,
+            pixels: image
+                .pixels
+                .iter()
+                .map(|[r, g, b]| Color::new(decode(*r), decode(*g), decode(*b)))
+                .collect(),
+            wrap,
+        }
+    }
+
+    // Reads a PNG or PPM file.
+    pub fn load(path: &Path, wrap: WrapMode) -> io::Result<Self> {
+        let image = read_image(path)?;
+        if image.width == 0 || image.height == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "image is empty"));
+        }
+        Ok(Self::new(&image, wrap))
+    }
+
+    fn pixel(&self, x: i64, y: i64) -> Vec3 {
+        let x = self.wrap.wrap(x, self.width);
+        let y = self.wrap.wrap(y, self.height);
+        self.pixels[y * self.width as usize + x].vec
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _point: Vec3) -> Color {
+        // pixel centers are at half integer coordinates
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        let (left, top) = (x.floor(), y.floor());
+        let (x_level, y_level) = (x - left, y - top);
+        let (left, top) = (left as i64, top as i64);
+        let upper = lerp(x_level, self.pixel(left, top), self.pixel(left + 1, top));
+        let lower = lerp(
+            x_level,
+            self.pixel(left, top + 1),
+            self.pixel(left + 1, top + 1),
+        );
+        Color::from_vec(lerp(y_level, upper, lower))
+    }
+}
+
+// How a noise texture turns Perlin noise into a level from 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoisePattern {
+    // plain noise, a soft blotchy pattern
+    Noise,
+    // fractal noise, like a cloudy sky
+    Turbulence,
+    // bands along z, distorted by turbulence
+    Marble,
+    // rings around the y axis, distorted by turbulence
+    Wood,
+}
+
+// A procedural texture which blends between two colors by a pattern made from
+// Perlin noise. Scale is the pattern's frequency, so bigger is finer.
+pub struct NoiseTexture {
+    perlin: Perlin,
+    pattern: NoisePattern,
+    scale: f64,
+    octaves: u32,
+    low: Color,
+    high: Color,
+}
+
+impl NoiseTexture {
+    pub fn new(
+        pattern: NoisePattern,
+        scale: f64,
+        octaves: u32,
+        seed: u64,
+        low: Vec3,
+        high: Vec3,
+    ) -> Self {
+        Self {
+            perlin: Perlin::new(seed),
+            pattern,
+            scale,
+            octaves,
+            low: Color::from_vec(low),
+            high: Color::from_vec(high),
+        }
+    }
+
+    fn level(&self, point: Vec3) -> f64 {
+        let point = point * self.scale;
+        let level = match self.pattern {
+            NoisePattern::Noise => 0.5 * (1.0 + self.perlin.noise(point)),
+            NoisePattern::Turbulence => self.perlin.turbulence(point, self.octaves),
+            NoisePattern::Marble => {
+                let turbulence = self.perlin.turbulence(point, self.octaves);
+                0.5 * (1.0 + (point.2 + 10.0 * turbulence).sin())
+            }
+            NoisePattern::Wood => {
+                let turbulence = self.perlin.turbulence(point, self.octaves);
+                let rings = point.0.hypot(point.2) + 2.0 * turbulence;
+                rings - rings.floor()
+            }
+        };
+        level.clamp(0.0, 1.0)
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, point: Vec3) -> Color {
+        Color::from_vec(lerp(self.level(point), self.low.vec, self.high.vec))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Color, b: Vec3) {
+        assert!((a.vec - b).length() < 1e-9, "{} != {}", a.vec, b);
+    }
+
+    // 2x2, with linear values chosen to survive the sRGB round trip exactly
+    fn test_image(wrap: WrapMode) -> ImageTexture {
+        ImageTexture::new(
+            &DecodedImage {
+                width: 2,
+                height: 2,
+                max_value: 255,
+                pixels: vec![[255, 0, 0], [0, 255, 0], [0, 0, 255], [0, 0, 0]],
+            },
+            wrap,
+        )
+    }
+
+    #[test]
+    fn checker_alternates_between_cubes() {
+        let checker = Checker::new(
+            Arc::new(SolidColor::new(Vec3(1.0, 1.0, 1.0))),
+            Arc::new(SolidColor::new(Vec3(0.0, 0.0, 0.0))),
+            2.0,
+        );
+        let value = |point| checker.value(0.0, 0.0, point).vec;
+        assert_eq!(value(Vec3(0.5, 0.5, 0.5)), Vec3(1.0, 1.0, 1.0));
+        assert_eq!(value(Vec3(2.5, 0.5, 0.5)), Vec3(0.0, 0.0, 0.0));
+        assert_eq!(value(Vec3(2.5, 2.5, 0.5)), Vec3(1.0, 1.0, 1.0));
+        // no seam at the origin
+        assert_eq!(value(Vec3(-0.5, 0.5, 0.5)), Vec3(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn image_pixel_centers_get_exact_colors() {
+        let image = test_image(WrapMode::Repeat);
+        let origin = Vec3(0.0, 0.0, 0.0);
+        // the top row of the image is at v = 1
+        assert_near(image.value(0.25, 0.75, origin), Vec3(1.0, 0.0, 0.0));
+        assert_near(image.value(0.75, 0.75, origin), Vec3(0.0, 1.0, 0.0));
+        assert_near(image.value(0.25, 0.25, origin), Vec3(0.0, 0.0, 1.0));
+        assert_near(image.value(0.75, 0.25, origin), Vec3(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn image_interpolates_between_pixels() {
+        let image = test_image(WrapMode::Clamp);
+        let origin = Vec3(0.0, 0.0, 0.0);
+        assert_near(image.value(0.5, 0.75, origin), Vec3(0.5, 0.5, 0.0));
+        assert_near(image.value(0.5, 0.5, origin), Vec3(0.25, 0.25, 0.25));
+    }
+
+    #[test]
+    fn image_decodes_srgb() {
+        let image = ImageTexture::new(
+            &DecodedImage {
+                width: 1,
+                height: 1,
+                max_value: 255,
+                pixels: vec![[188, 188, 188]],
+            },
+            WrapMode::Repeat,
+        );
+        let value = image.value(0.5, 0.5, Vec3(0.0, 0.0, 0.0));
+        assert!((value.r() - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn wrap_modes_handle_out_of_range_uvs() {
+        let origin = Vec3(0.0, 0.0, 0.0);
+        let repeat = test_image(WrapMode::Repeat);
+        assert_near(repeat.value(1.25, 0.75, origin), Vec3(1.0, 0.0, 0.0));
+        assert_near(repeat.value(-0.25, 0.75, origin), Vec3(0.0, 1.0, 0.0));
+        // halfway between the right column and the left one it repeats as
+        assert_near(repeat.value(1.0, 0.75, origin), Vec3(0.5, 0.5, 0.0));
+        let clamp = test_image(WrapMode::Clamp);
+        assert_near(clamp.value(3.0, 0.75, origin), Vec3(0.0, 1.0, 0.0));
+        assert_near(clamp.value(-3.0, 0.75, origin), Vec3(1.0, 0.0, 0.0));
+        let mirror = test_image(WrapMode::Mirror);
+        assert_near(mirror.value(1.25, 0.75, origin), Vec3(0.0, 1.0, 0.0));
+        assert_near(mirror.value(2.25, 0.75, origin), Vec3(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn wraps_indices() {
+        assert_eq!(
+            (-3..6)
+                .map(|idx| WrapMode::Repeat.wrap(idx, 3))
+                .collect::<Vec<_>>(),
+            [0, 1, 2, 0, 1, 2, 0, 1, 2]
+        );
+        assert_eq!(
+            (-3..6)
+                .map(|idx| WrapMode::Clamp.wrap(idx, 3))
+                .collect::<Vec<_>>(),
+            [0, 0, 0, 0, 1, 2, 2, 2, 2]
+        );
+        assert_eq!(
+            (-3..6)
+                .map(|idx| WrapMode::Mirror.wrap(idx, 3))
+                .collect::<Vec<_>>(),
+            [2, 1, 0, 0, 1, 2, 2, 1, 0]
+        );
+    }
+
+    #[test]
+    fn noise_textures_stay_between_their_colors() {
+        let (low, high) = (Vec3(0.1, 0.2, 0.3), Vec3(0.9, 0.6, 0.3));
+        for pattern in [
+            NoisePattern::Noise,
+            NoisePattern::Turbulence,
+            NoisePattern::Marble,
+            NoisePattern::Wood,
+        ] {
+            let texture = NoiseTexture::new(pattern, 4.0, 7, 0, low, high);
+            for idx in 0..1000 {
+                let t = idx as f64 * 0.013;
+                let color = texture.value(0.0, 0.0, Vec3(t, t * 0.5 - 3.0, -t)).vec;
+                for axis in 0..3 {
+                    let range =
+                        low[axis].min(high[axis]) - 1e-12..high[axis].max(low[axis]) + 1e-12;
+                    assert!(range.contains(&color[axis]), "{:?}: {}", pattern, color);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn noise_textures_are_reproducible() {
+        let point = Vec3(0.3, -1.2, 2.5);
+        let texture = |seed| {
+            NoiseTexture::new(
+                NoisePattern::Marble,
+                2.0,
+                7,
+                seed,
+                Vec3(0.0, 0.0, 0.0),
+                Vec3(1.0, 1.0, 1.0),
+            )
+            .value(0.0, 0.0, point)
+            .vec
+        };
+        assert_eq!(texture(3), texture(3));
+        assert_ne!(texture(3), texture(4));
+    }
+}
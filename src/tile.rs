@@ -0,0 +1,116 @@
+// A rectangle of pixels, rendered by one thread as a single piece of work.
+// (x, y) is its top left pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        (self.y..self.y + self.height)
+            .flat_map(move |row| (self.x..self.x + self.width).map(move |col| (col, row)))
+    }
+}
+
+// Covers the image with tiles of tile_size pixels square, smaller along its
+// right and bottom edges. They're ordered in a spiral out from the middle of
+// the image, so the interesting part usually appears first and the tiles in
+// progress at any time are close together.
+pub fn spiral_tiles(image_width: u32, image_height: u32, tile_size: u32) -> Vec<Tile> {
+    let tile_size = tile_size.max(1);
+    let columns = image_width.div_ceil(tile_size) as i64;
+    let rows = image_height.div_ceil(tile_size) as i64;
+    let tile_count = (columns * rows) as usize;
+    let tile = |column: i64, row: i64| {
+        let (x, y) = (column as u32 * tile_size, row as u32 * tile_size);
+        Tile {
+            x,
+            y,
+            width: tile_size.min(image_width - x),
+            height: tile_size.min(image_height - y),
+        }
+    };
+
+    // Walk right 1, down 1, left 2, up 2, right 3 and so on, keeping the
+    // tiles which are inside the image.
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let (mut column, mut row) = ((columns - 1) / 2, (rows - 1) / 2);
+    let mut tiles = Vec::with_capacity(tile_count);
+    let mut direction_idx = 0;
+    let mut run_length = 1;
+    while tiles.len() < tile_count {
+        for _ in 0..2 {
+            let (column_step, row_step) = directions[direction_idx % 4];
+            for _ in 0..run_length {
+                if (0..columns).contains(&column) && (0..rows).contains(&row) {
+                    tiles.push(tile(column, row));
+                }
+                column += column_step;
+                row += row_step;
+            }
+            direction_idx += 1;
+        }
+        run_length += 1;
+    }
+    tiles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn covers_every_pixel_once(tiles: &[Tile], width: u32, height: u32) -> bool {
+        let mut counts = vec![0; width as usize * height as usize];
+        for tile in tiles {
+            for (col, row) in tile.pixels() {
+                if col >= width || row >= height {
+                    return false;
+                }
+                counts[(row * width + col) as usize] += 1;
+            }
+        }
+        counts.iter().all(|count| *count == 1)
+    }
+
+    #[test]
+    fn tiles_cover_the_image() {
+        for (width, height, tile_size) in [(64, 64, 32), (100, 37, 16), (5, 90, 8), (7, 3, 32)] {
+            let tiles = spiral_tiles(width, height, tile_size);
+            assert!(
+                covers_every_pixel_once(&tiles, width, height),
+                "{}x{} in tiles of {}",
+                width,
+                height,
+                tile_size
+            );
+        }
+        assert!(spiral_tiles(0, 0, 32).is_empty());
+    }
+
+    #[test]
+    fn tiles_spiral_out_from_the_middle() {
+        let tiles = spiral_tiles(50, 50, 10);
+        assert_eq!(
+            tiles[0],
+            Tile {
+                x: 20,
+                y: 20,
+                width: 10,
+                height: 10
+            }
+        );
+        // each tile is next to the one before, getting further from the
+        // middle
+        for pair in tiles.windows(2) {
+            let (dx, dy) = (pair[1].x.abs_diff(pair[0].x), pair[1].y.abs_diff(pair[0].y));
+            assert_eq!(dx + dy, 10);
+        }
+        let distance = |tile: &Tile| tile.x.abs_diff(20).max(tile.y.abs_diff(20));
+        assert!(tiles
+            .windows(2)
+            .all(|pair| distance(&pair[1]) >= distance(&pair[0])));
+    }
+}
@@ -0,0 +1,227 @@
+use crate::color::Color;
+use crate::validate;
+use crate::vec3::Vec3;
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+// How radiance is squeezed into the displayable 0 to 1 range before being
+// written to a low dynamic range image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ToneMapOperator {
+    // anything above 1 is just cut off
+    #[default]
+    Clamp,
+    Reinhard,
+    // Reinhard, but reaching 1 at the white point instead of at infinity
+    ReinhardExtended,
+    // John Hable's filmic curve from Uncharted 2
+    Hable,
+    // Stephen Hill's fit of the ACES reference and output transforms
+    Aces,
+}
+
+impl ToneMapOperator {
+    pub const NAMES: [&'static str; 5] =
+        ["clamp", "reinhard", "reinhard-extended", "hable", "aces"];
+
+    const ALL: [Self; 5] = [
+        Self::Clamp,
+        Self::Reinhard,
+        Self::ReinhardExtended,
+        Self::Hable,
+        Self::Aces,
+    ];
+}
+
+impl fmt::Display for ToneMapOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let idx = Self::ALL
+            .iter()
+            .position(|operator| operator == self)
+            .unwrap();
+        write!(f, "{}", Self::NAMES[idx])
+    }
+}
+
+impl FromStr for ToneMapOperator {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match Self::NAMES.iter().position(|known| *known == name) {
+            Some(idx) => Ok(Self::ALL[idx]),
+            None => Err(format!(
+                "unknown tone mapping operator '{}' (known operators: {})",
+                name,
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    // in stops, so each +1 doubles the brightness
+    #[serde(deserialize_with = "validate::finite")]
+    pub exposure: f64,
+    // the radiance that maps to white with the extended Reinhard operator
+    #[serde(deserialize_with = "validate::positive")]
+    pub white_point: f64,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            operator: ToneMapOperator::Clamp,
+            exposure: 0.0,
+            white_point: 4.0,
+        }
+    }
+}
+
+fn hable_partial(x: f64) -> f64 {
+    const A: f64 = 0.15; // shoulder strength
+    const B: f64 = 0.50; // linear strength
+    const C: f64 = 0.10; // linear angle
+    const D: f64 = 0.20; // toe strength
+    const E: f64 = 0.02; // toe numerator
+    const F: f64 = 0.30; // toe denominator
+    (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F
+}
+
+fn hable(x: f64) -> f64 {
+    const EXPOSURE_BIAS: f64 = 2.0;
+    const LINEAR_WHITE: f64 = 11.2;
+    hable_partial(x * EXPOSURE_BIAS) / hable_partial(LINEAR_WHITE)
+}
+
+fn mat3_mul(matrix: [[f64; 3]; 3], v: Vec3) -> Vec3 {
+    let row = |r: [f64; 3]| r[0] * v.0 + r[1] * v.1 + r[2] * v.2;
+    Vec3(row(matrix[0]), row(matrix[1]), row(matrix[2]))
+}
+
+// sRGB => XYZ => D65_2_D60 => AP1 => RRT_SAT
+const ACES_INPUT: [[f64; 3]; 3] = [
+    [0.59719, 0.35458, 0.04823],
+    [0.07600, 0.90834, 0.01566],
+    [0.02840, 0.13383, 0.83777],
+];
+
+// ODT_SAT => XYZ => D60_2_D65 => sRGB
+const ACES_OUTPUT: [[f64; 3]; 3] = [
+    [1.60475, -0.53108, -0.07367],
+    [-0.10208, 1.10813, -0.00605],
+    [-0.00327, -0.07276, 1.07602],
+];
+
+fn aces_rrt_and_odt_fit(x: f64) -> f64 {
+    (x * (x + 0.0245786) - 0.000090537) / (x * (0.983729 * x + 0.4329510) + 0.238081)
+}
+
+impl ToneMapping {
+    // Maps linear radiance to linear display values between 0 and 1.
+    pub fn map(&self, color: Color) -> Color {
+        // negative radiance isn't meaningful, and would upset the curves
+        let exposed = color.vec.max(Vec3(0.0, 0.0, 0.0)) * 2f64.powf(self.exposure);
+        let per_component = |f: &dyn Fn(f64) -> f64| {
+            let Vec3(r, g, b) = exposed;
+            Vec3(f(r), f(g), f(b))
+        };
+        let mapped = match self.operator {
+            ToneMapOperator::Clamp => per_component(&|x| x),
+            ToneMapOperator::Reinhard => per_component(&|x| x / (1.0 + x)),
+            ToneMapOperator::ReinhardExtended => {
+                let white_squared = self.white_point * self.white_point;
+                per_component(&|x| x * (1.0 + x / white_squared) / (1.0 + x))
+            }
+            ToneMapOperator::Hable => per_component(&hable),
+            ToneMapOperator::Aces => {
+                let v = mat3_mul(ACES_INPUT, exposed);
+                let v = Vec3(
+                    aces_rrt_and_odt_fit(v.0),
+                    aces_rrt_and_odt_fit(v.1),
+                    aces_rrt_and_odt_fit(v.2),
+                );
+                mat3_mul(ACES_OUTPUT, v)
+            }
+        };
+        Color::new(
+            mapped.0.clamp(0.0, 1.0),
+            mapped.1.clamp(0.0, 1.0),
+            mapped.2.clamp(0.0, 1.0),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map_gray(operator: ToneMapOperator, value: f64) -> f64 {
+        let tone_mapping = ToneMapping {
+            operator,
+            ..ToneMapping::default()
+        };
+        tone_mapping.map(Color::new(value, value, value)).r()
+    }
+
+    #[test]
+    fn parses_and_displays_operator_names() {
+        for name in ToneMapOperator::NAMES {
+            assert_eq!(name.parse::<ToneMapOperator>().unwrap().to_string(), name);
+        }
+        assert_eq!(
+            "filmic".parse::<ToneMapOperator>(),
+            Err("unknown tone mapping operator 'filmic' (known operators: clamp, reinhard, reinhard-extended, hable, aces)".to_string())
+        );
+    }
+
+    #[test]
+    fn every_operator_maps_into_display_range_monotonically() {
+        for operator in ToneMapOperator::ALL {
+            assert!(map_gray(operator, 0.0).abs() < 1e-3, "{}", operator);
+            let mut previous = 0.0;
+            for step in 1..200 {
+                let mapped = map_gray(operator, step as f64 * 0.1);
+                assert!((0.0..=1.0).contains(&mapped), "{}", operator);
+                assert!(mapped >= previous, "{} isn't monotonic", operator);
+                previous = mapped;
+            }
+            assert_eq!(map_gray(operator, -1.0), map_gray(operator, 0.0));
+        }
+    }
+
+    #[test]
+    fn operators_match_their_formulas() {
+        assert_eq!(map_gray(ToneMapOperator::Clamp, 0.25), 0.25);
+        assert_eq!(map_gray(ToneMapOperator::Clamp, 3.0), 1.0);
+        assert_eq!(map_gray(ToneMapOperator::Reinhard, 1.0), 0.5);
+        assert_eq!(map_gray(ToneMapOperator::Reinhard, 3.0), 0.75);
+        // the white point maps exactly to white
+        assert!((map_gray(ToneMapOperator::ReinhardExtended, 4.0) - 1.0).abs() < 1e-12);
+        assert!(map_gray(ToneMapOperator::ReinhardExtended, 1.0) > 0.5);
+        assert!((map_gray(ToneMapOperator::Hable, 5.6) - 1.0).abs() < 1e-12);
+        assert!((map_gray(ToneMapOperator::Aces, 0.18) - 0.1056).abs() < 1e-3);
+        assert!(map_gray(ToneMapOperator::Aces, 10.0) > 0.95);
+    }
+
+    #[test]
+    fn exposure_is_in_stops() {
+        let tone_mapping = ToneMapping {
+            exposure: 2.0,
+            ..ToneMapping::default()
+        };
+        assert_eq!(
+            tone_mapping.map(Color::new(0.125, 0.0, 0.05)).vec,
+            Vec3(0.5, 0.0, 0.2)
+        );
+        let tone_mapping = ToneMapping {
+            exposure: -1.0,
+            ..ToneMapping::default()
+        };
+        assert_eq!(tone_mapping.map(Color::new(1.0, 1.0, 1.0)).r(), 0.5);
+    }
+}
@@ -0,0 +1,295 @@
+use crate::aabb::Aabb;
+use crate::bvh::BvhNode;
+use crate::hittable::{Hit, Hittable};
+use crate::hittable_list::HittableList;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use std::ops::Range;
+use std::sync::Arc;
+
+// Determinants smaller than this mean the ray is (nearly) parallel to the
+// plane of the triangle.
+const PARALLEL_EPSILON: f64 = 1e-12;
+
+// Indices into the vertex buffers of a MeshData for one triangle. Positions,
+// normals and texture coordinates each have their own index, as in OBJ files.
+#[derive(Debug, Clone, Copy)]
+pub struct Face {
+    pub positions: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+}
+
+impl Face {
+    pub fn new(positions: [usize; 3]) -> Self {
+        Self {
+            positions,
+            normals: None,
+            uvs: None,
+        }
+    }
+}
+
+// Vertex and index buffers, shared between all the triangles of a mesh.
+#[derive(Debug, Clone, Default)]
+pub struct MeshData {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub faces: Vec<Face>,
+}
+
+#[derive(Clone)]
+pub struct Triangle {
+    mesh: Arc<MeshData>,
+    face_idx: usize,
+    material: Arc<dyn Material + Send + Sync>,
+}
+
+impl Triangle {
+    // A lone triangle, wound counter-clockwise when viewed from the front.
+    pub fn new(vertices: [Vec3; 3], material: Arc<dyn Material + Send + Sync>) -> Self {
+        let mesh = MeshData {
+            positions: vertices.to_vec(),
+            faces: vec![Face::new([0, 1, 2])],
+            ..MeshData::default()
+        };
+        Self {
+            mesh: Arc::new(mesh),
+            face_idx: 0,
+            material,
+        }
+    }
+
+    fn face(&self) -> &Face {
+        &self.mesh.faces[self.face_idx]
+    }
+
+    fn vertices(&self) -> [Vec3; 3] {
+        self.face().positions.map(|idx| self.mesh.positions[idx])
+    }
+}
+
+impl Hittable for Triangle {
+    // Möller–Trumbore - solves for the ray parameter and the barycentric
+    // coordinates of the intersection simultaneously, using Cramer's rule.
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let [p0, p1, p2] = self.vertices();
+        let edge1 = p1 - p0;
+        let edge2 = p2 - p0;
+        let p_vec = ray.vector.cross(edge2);
+        let determinant = edge1.dot(p_vec);
+        if determinant.abs() < PARALLEL_EPSILON {
+            return None;
+        }
+        let inverse_determinant = 1.0 / determinant;
+        let t_vec = ray.origin - p0;
+        let b1 = t_vec.dot(p_vec) * inverse_determinant;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+        let q_vec = t_vec.cross(edge1);
+        let b2 = ray.vector.dot(q_vec) * inverse_determinant;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+        let t = edge2.dot(q_vec) * inverse_determinant;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let b0 = 1.0 - b1 - b2;
+        let face = self.face();
+        let hit = Hit::new(
+            edge1.cross(edge2).unit_vector(),
+            ray.at(t),
+            ray,
+            t,
+            Arc::clone(&self.material),
+        );
+        let hit = match face.normals {
+            Some(normals) => {
+                let [n0, n1, n2] = normals.map(|idx| self.mesh.normals[idx]);
+                hit.with_shading_normal((n0 * b0 + n1 * b1 + n2 * b2).unit_vector())
+            }
+            None => hit,
+        };
+        let hit = match face.uvs {
+            Some(uvs) => {
+                let [uv0, uv1, uv2] = uvs.map(|idx| self.mesh.uvs[idx]);
+                hit.with_uv(
+                    uv0.0 * b0 + uv1.0 * b1 + uv2.0 * b2,
+                    uv0.1 * b0 + uv1.1 * b1 + uv2.1 * b2,
+                )
+            }
+            None => hit.with_uv(b1, b2),
+        };
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [p0, p1, p2] = self.vertices();
+        Some(Aabb::new(p0.min(p1).min(p2), p0.max(p1).max(p2)).padded())
+    }
+}
+
+// A mesh of triangles sharing one set of vertex buffers and one material.
+pub struct TriangleMesh {
+    bvh: BvhNode,
+}
+
+impl TriangleMesh {
+    pub fn new(mesh: MeshData, material: Arc<dyn Material + Send + Sync>) -> Self {
+        let face_count = mesh.faces.len();
+        Self::from_faces(Arc::new(mesh), 0..face_count, material)
+    }
+
+    // A mesh made of some of the faces of a MeshData, which can be shared with
+    // other meshes using different materials.
+    pub fn from_faces(
+        mesh: Arc<MeshData>,
+        faces: Range<usize>,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> Self {
+        let mut triangles = HittableList::new();
+        for face_idx in faces {
+            triangles.add(Triangle {
+                mesh: Arc::clone(&mesh),
+                face_idx,
+                material: Arc::clone(&material),
+            });
+        }
+        Self {
+            bvh: BvhNode::new(&triangles),
+        }
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        self.bvh.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn material() -> Arc<dyn Material + Send + Sync> {
+        Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5)))
+    }
+
+    fn unit_triangle() -> Triangle {
+        Triangle::new(
+            [
+                Vec3(0.0, 0.0, 0.0),
+                Vec3(1.0, 0.0, 0.0),
+                Vec3(0.0, 1.0, 0.0),
+            ],
+            material(),
+        )
+    }
+
+    fn ray_down_z_through(x: f64, y: f64) -> Ray {
+        Ray::new(Vec3(x, y, 5.0), Vec3(0.0, 0.0, -1.0))
+    }
+
+    #[test]
+    fn ray_through_triangle_hits() {
+        let hit = unit_triangle()
+            .hit(ray_down_z_through(0.25, 0.25), 0.0, f64::INFINITY)
+            .unwrap();
+        assert_eq!(hit.ray_t, 5.0);
+        assert_eq!(hit.hit_point, Vec3(0.25, 0.25, 0.0));
+        assert_eq!(hit.normal, Vec3(0.0, 0.0, 1.0));
+        assert!(hit.front_face);
+    }
+
+    #[test]
+    fn ray_outside_triangle_misses() {
+        let triangle = unit_triangle();
+        assert!(triangle
+            .hit(ray_down_z_through(0.6, 0.6), 0.0, f64::INFINITY)
+            .is_none());
+        assert!(triangle
+            .hit(ray_down_z_through(-0.1, 0.5), 0.0, f64::INFINITY)
+            .is_none());
+    }
+
+    #[test]
+    fn ray_parallel_to_triangle_misses() {
+        let ray = Ray::new(Vec3(-1.0, 0.25, 0.0), Vec3(1.0, 0.0, 0.0));
+        assert!(unit_triangle().hit(ray, 0.0, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn hit_from_behind_is_back_face() {
+        let ray = Ray::new(Vec3(0.25, 0.25, -5.0), Vec3(0.0, 0.0, 1.0));
+        let hit = unit_triangle().hit(ray, 0.0, f64::INFINITY).unwrap();
+        assert!(!hit.front_face);
+        assert_eq!(hit.normal, Vec3(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn interpolates_vertex_normals_and_uvs() {
+        let mesh = MeshData {
+            positions: vec![
+                Vec3(0.0, 0.0, 0.0),
+                Vec3(1.0, 0.0, 0.0),
+                Vec3(0.0, 1.0, 0.0),
+            ],
+            normals: vec![
+                Vec3(0.0, 0.0, 1.0),
+                Vec3(1.0, 0.0, 0.0),
+                Vec3(0.0, 1.0, 0.0),
+            ],
+            uvs: vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)],
+            faces: vec![Face {
+                positions: [0, 1, 2],
+                normals: Some([0, 1, 2]),
+                uvs: Some([0, 1, 2]),
+            }],
+        };
+        let mesh = TriangleMesh::new(mesh, material());
+        let hit = mesh
+            .hit(ray_down_z_through(0.5, 0.5), 0.0, f64::INFINITY)
+            .unwrap();
+        let expected_normal = Vec3(1.0, 1.0, 0.0).unit_vector();
+        assert!((hit.normal - expected_normal).length() < 1e-12);
+        assert!((hit.u - 1.0).abs() < 1e-12);
+        assert!((hit.v - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn mesh_shares_vertices_between_faces() {
+        // a unit square made of two triangles
+        let mesh = MeshData {
+            positions: vec![
+                Vec3(0.0, 0.0, 0.0),
+                Vec3(1.0, 0.0, 0.0),
+                Vec3(1.0, 1.0, 0.0),
+                Vec3(0.0, 1.0, 0.0),
+            ],
+            faces: vec![Face::new([0, 1, 2]), Face::new([0, 2, 3])],
+            ..MeshData::default()
+        };
+        let mesh = TriangleMesh::new(mesh, material());
+        for (x, y) in [(0.9, 0.1), (0.1, 0.9), (0.5, 0.5)] {
+            assert!(mesh
+                .hit(ray_down_z_through(x, y), 0.0, f64::INFINITY)
+                .is_some());
+        }
+        assert!(mesh
+            .hit(ray_down_z_through(1.1, 0.5), 0.0, f64::INFINITY)
+            .is_none());
+        let bounding_box = mesh.bounding_box().unwrap();
+        assert_eq!(bounding_box.min.0, 0.0);
+        assert_eq!(bounding_box.max.1, 1.0);
+    }
+}
@@ -32,3 +32,31 @@ pub fn clamp(value: f64, min: f64, max: f64) -> f64 {
         value
     }
 }
+
+// A fresh directory for a test's files, named after the test and the process
+// so that tests running at the same time never share one, and removed again
+// when dropped.
+#[cfg(test)]
+pub struct TempDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("rtiow-{}-{}", name, std::process::id()));
+        // anything left by an earlier run which had the same process id
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
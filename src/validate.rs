@@ -0,0 +1,119 @@
+// Deserializers for settings which would make nonsense of a render, e.g. a
+// zero image width or a NaN exposure. They reject the value as it's read,
+// rather than the scene checking it afterwards, so that the TOML error points
+// at the value itself. The message doesn't repeat the key, since the error
+// quotes the line with it.
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+
+fn check<'de, D, T>(
+    deserializer: D,
+    is_valid: impl Fn(&T) -> bool,
+    requirement: &str,
+) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    let value = T::deserialize(deserializer)?;
+    if is_valid(&value) {
+        Ok(value)
+    } else {
+        Err(D::Error::custom(format!("must be {}", requirement)))
+    }
+}
+
+pub fn non_zero<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    check(deserializer, |value: &u32| *value != 0, "greater than 0")
+}
+
+pub fn finite<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    check(
+        deserializer,
+        |value: &f64| value.is_finite(),
+        "a finite number",
+    )
+}
+
+pub fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    check(
+        deserializer,
+        |value: &f64| *value > 0.0 && value.is_finite(),
+        "a finite number greater than 0",
+    )
+}
+
+pub fn finite_point<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[f64; 3], D::Error> {
+    check(
+        deserializer,
+        |point: &[f64; 3]| point.iter().all(|value| value.is_finite()),
+        "three finite numbers",
+    )
+}
+
+// in degrees, so a full half turn would see nothing
+pub fn field_of_view<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    check(
+        deserializer,
+        |degrees: &f64| *degrees > 0.0 && *degrees < 180.0,
+        "an angle between 0 and 180 degrees",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Settings {
+        #[serde(default, deserialize_with = "non_zero")]
+        count: u32,
+        #[serde(default, deserialize_with = "finite")]
+        offset: f64,
+        #[serde(default, deserialize_with = "positive")]
+        size: f64,
+        #[serde(default, deserialize_with = "finite_point")]
+        point: [f64; 3],
+        #[serde(default, deserialize_with = "field_of_view")]
+        fov: f64,
+    }
+
+    fn error(source: &str) -> Option<String> {
+        toml::from_str::<Settings>(source)
+            .err()
+            .map(|err| err.to_string())
+    }
+
+    #[test]
+    fn accepts_sensible_values() {
+        let settings: Settings = toml::from_str(
+            "count = 3\noffset = -2.0\nsize = 0.5\npoint = [1.0, -1.0, 0.0]\nfov = 90.0\n",
+        )
+        .unwrap();
+        assert_eq!(
+            (settings.count, settings.offset, settings.size),
+            (3, -2.0, 0.5)
+        );
+        assert_eq!((settings.point, settings.fov), ([1.0, -1.0, 0.0], 90.0));
+    }
+
+    #[test]
+    fn rejects_nonsense_where_it_is() {
+        for (source, message) in [
+            ("count = 0", "must be greater than 0"),
+            ("offset = nan", "must be a finite number"),
+            ("offset = -inf", "must be a finite number"),
+            ("size = 0.0", "must be a finite number greater than 0"),
+            ("size = -1.0", "must be a finite number greater than 0"),
+            ("size = inf", "must be a finite number greater than 0"),
+            ("point = [0.0, -inf, 0.0]", "must be three finite numbers"),
+            ("fov = 0.0", "must be an angle between 0 and 180 degrees"),
+            ("fov = 180.0", "must be an angle between 0 and 180 degrees"),
+            ("fov = nan", "must be an angle between 0 and 180 degrees"),
+        ] {
+            let error = error(&format!("\n{}\n", source)).unwrap();
+            assert!(error.contains("line 2"), "{}", error);
+            assert!(error.contains(message), "{}: {}", source, error);
+        }
+    }
+}
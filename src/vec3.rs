@@ -1,5 +1,5 @@
+use crate::sampler::Sampler;
 use crate::utils::{range_width, remap};
-use rand::random;
 use std::{fmt, ops, ops::Range};
 
 #[derive(Debug, Copy, Clone)]
@@ -67,18 +67,21 @@ impl ops::DivAssign<f64> for Vec3 {
     }
 }
 
-impl Vec3 {
-    pub fn random() -> Self {
-        Self(random(), random(), random())
+impl ops::Index<usize> for Vec3 {
+    type Output = f64;
+    fn index(&self, axis: usize) -> &f64 {
+        match axis {
+            0 => &self.0,
+            1 => &self.1,
+            2 => &self.2,
+            _ => panic!("no axis {} on a Vec3", axis),
+        }
     }
+}
 
-    pub fn random_in_unit_disk() -> Self {
-        loop {
-            let v = Vec3(random(), random(), 0.0);
-            if v.length_squared() < 1.0 {
-                return v;
-            }
-        }
+impl Vec3 {
+    pub fn random(sampler: &mut dyn Sampler) -> Self {
+        Self(sampler.get_1d(), sampler.get_1d(), sampler.get_1d())
     }
 
     pub fn remap(&self, original_range: &Range<f64>, new_range: &Range<f64>) -> Self {
@@ -89,8 +92,8 @@ impl Vec3 {
         )
     }
 
-    pub fn random_from_range(range: &Range<f64>) -> Self {
-        let rand_in_range = || -> f64 { range.start + random::<f64>() * range_width(range) };
+    pub fn random_from_range(range: &Range<f64>, sampler: &mut dyn Sampler) -> Self {
+        let mut rand_in_range = || -> f64 { range.start + sampler.get_1d() * range_width(range) };
         Self(rand_in_range(), rand_in_range(), rand_in_range())
     }
 
@@ -123,6 +126,22 @@ impl Vec3 {
     pub fn unit_vector(self) -> Self {
         self / self.length()
     }
+    // component-wise minimum
+    pub fn min(self, other: Self) -> Self {
+        Self(
+            self.0.min(other.0),
+            self.1.min(other.1),
+            self.2.min(other.2),
+        )
+    }
+    // component-wise maximum
+    pub fn max(self, other: Self) -> Self {
+        Self(
+            self.0.max(other.0),
+            self.1.max(other.1),
+            self.2.max(other.2),
+        )
+    }
     pub fn is_near_zero(self) -> bool {
         let s = 1e-8;
         self.0.abs() < s && self.1.abs() < s && self.2.abs() < s
@@ -249,6 +268,20 @@ mod tests {
         assert_eq!(v1.unit_vector(), Vec3(0.0, 1.0, 0.0));
     }
 
+    #[test]
+    fn can_index_a_vector_by_axis() {
+        let v1 = Vec3(1.0, 2.0, 3.0);
+        assert_eq!((v1[0], v1[1], v1[2]), (1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn can_get_component_wise_min_and_max() {
+        let v1 = Vec3(1.0, 5.0, 3.0);
+        let v2 = Vec3(4.0, 2.0, 6.0);
+        assert_eq!(v1.min(v2), Vec3(1.0, 2.0, 3.0));
+        assert_eq!(v1.max(v2), Vec3(4.0, 5.0, 6.0));
+    }
+
     #[test]
     fn can_display_a_vector() {
         let v1 = Vec3(1.1, 2.2, 3.3);
@@ -0,0 +1,199 @@
+use rtiow::background::Background;
+use rtiow::hittable_list::HittableList;
+use rtiow::material::DiffuseLight;
+use rtiow::sphere::ObjectSphere;
+use rtiow::tonemap::ToneMapping;
+use rtiow::vec3::Vec3;
+use rtiow::{CameraSettings, Framebuffer, RenderSettings, Renderer, Scene};
+use std::path::Path;
+use std::sync::Arc;
+
+fn small_settings() -> RenderSettings {
+    RenderSettings {
+        image_width: 40,
+        samples_per_pixel: 4,
+        ..RenderSettings::default()
+    }
+}
+
+fn pixel_values(framebuffer: &Framebuffer) -> Vec<Vec3> {
+    framebuffer.pixels().iter().map(|color| color.vec).collect()
+}
+
+fn random_spheres(seed: u64, thread_count: usize) -> Vec<Vec3> {
+    let settings = RenderSettings {
+        seed,
+        ..small_settings()
+    };
+    let scene = Scene::random_spheres(settings, 1);
+    pixel_values(&Renderer::new(&scene).with_threads(thread_count).render())
+}
+
+#[test]
+fn renders_a_scene_file() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/cornell_box.toml");
+    let mut scene = Scene::load(&path).unwrap();
+    scene.settings = RenderSettings {
+        background: scene.settings.background,
+        ..small_settings()
+    };
+    let framebuffer = Renderer::new(&scene).with_threads(2).render();
+    assert_eq!((framebuffer.width(), framebuffer.height()), (40, 40));
+    let pixels = pixel_values(&framebuffer);
+    assert!(pixels
+        .iter()
+        .all(|pixel| (0..3).all(|axis| pixel[axis].is_finite() && pixel[axis] >= 0.0)));
+    // the box is lit
+    assert!(pixels.iter().any(|pixel| pixel.length() > 0.1));
+}
+
+// A light filling the whole view, so every sample of every pixel is the same.
+fn light_filling_the_view(settings: RenderSettings) -> Scene {
+    let mut world = HittableList::new();
+    world.add(ObjectSphere::new(
+        100.0,
+        Vec3(0.0, 0.0, -101.0),
+        Arc::new(DiffuseLight::new(Vec3(0.5, 2.0, 4.0))),
+    ));
+    Scene {
+        world,
+        lights: HittableList::new(),
+        camera_settings: CameraSettings {
+            aspect_ratio: 2.0,
+            vertical_fov_degrees: 20.0,
+            look_from: Vec3(0.0, 0.0, 0.0),
+            look_at: Vec3(0.0, 0.0, -1.0),
+            view_up: Vec3(0.0, 1.0, 0.0),
+            lens_radius: 0.0,
+            focus_dist: 1.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+        },
+        settings: RenderSettings {
+            background: Background::Solid { color: [0.0; 3] },
+            ..settings
+        },
+        tone_mapping: ToneMapping::default(),
+    }
+}
+
+#[test]
+fn renders_a_scene_built_in_code() {
+    let scene = light_filling_the_view(small_settings());
+    let renderer = Renderer::new(&scene);
+    assert_eq!(renderer.camera().image_height, 20);
+    let framebuffer = renderer.render();
+    assert_eq!((framebuffer.width(), framebuffer.height()), (40, 20));
+    for pixel in pixel_values(&framebuffer) {
+        assert!((pixel - Vec3(0.5, 2.0, 4.0)).length() < 1e-9, "{}", pixel);
+    }
+}
+
+#[test]
+fn renders_depend_only_on_the_seed() {
+    let image = random_spheres(3, 1);
+    for thread_count in [2, 7] {
+        assert_eq!(image, random_spheres(3, thread_count));
+    }
+    assert_ne!(image, random_spheres(4, 1));
+}
+
+#[test]
+fn reports_each_tile_as_it_is_finished() {
+    let scene = Scene::random_spheres(small_settings(), 1);
+    let renderer = Renderer::new(&scene).with_threads(3).with_tile_size(8);
+    let mut reports = Vec::new();
+    renderer.render_with_progress(|progress| reports.push(*progress));
+    let image_height = renderer.camera().image_height;
+    let tile_count = 5 * image_height.div_ceil(8);
+    assert_eq!(reports.len(), tile_count as usize);
+    let mut pixel_count = 0;
+    for (idx, progress) in reports.iter().enumerate() {
+        assert_eq!(progress.tiles_done, idx as u32 + 1);
+        assert_eq!(progress.tile_count, tile_count);
+        assert!(progress.thread_idx < 3);
+        pixel_count += progress.tile.width * progress.tile.height;
+    }
+    assert_eq!(pixel_count, 40 * image_height);
+}
+
+#[test]
+fn tile_size_makes_no_difference() {
+    let scene = Scene::random_spheres(small_settings(), 1);
+    let image = pixel_values(&Renderer::new(&scene).render());
+    for tile_size in [1, 7, 64] {
+        let renderer = Renderer::new(&scene).with_tile_size(tile_size);
+        assert_eq!(image, pixel_values(&renderer.render()));
+    }
+}
+
+#[test]
+fn progressive_rendering_ends_with_the_same_image() {
+    let settings = RenderSettings {
+        samples_per_pixel: 5,
+        ..small_settings()
+    };
+    let scene = Scene::random_spheres(settings, 1);
+    let renderer = Renderer::new(&scene).with_threads(2);
+    let mut passes = Vec::new();
+    let progressive = renderer.render_progressive(|samples_done, framebuffer| {
+        passes.push((samples_done, pixel_values(framebuffer)));
+    });
+    let samples_done: Vec<u32> = passes.iter().map(|(samples, _)| *samples).collect();
+    assert_eq!(samples_done, [1, 2, 4, 5]);
+    let image = pixel_values(&renderer.render());
+    assert_eq!(pixel_values(&progressive), image);
+    assert_eq!(passes.last().unwrap().1, image);
+    // the first pass is the image with one sample per pixel
+    let one_sample = RenderSettings {
+        samples_per_pixel: 1,
+        ..settings
+    };
+    let scene = Scene::random_spheres(one_sample, 1);
+    assert_eq!(passes[0].1, pixel_values(&Renderer::new(&scene).render()));
+}
+
+fn adaptive_settings() -> RenderSettings {
+    RenderSettings {
+        samples_per_pixel: 64,
+        adaptive_threshold: Some(0.05),
+        min_samples_per_pixel: 4,
+        ..small_settings()
+    }
+}
+
+#[test]
+fn adaptive_sampling_stops_early_where_there_is_no_noise() {
+    let scene = light_filling_the_view(adaptive_settings());
+    let framebuffer = Renderer::new(&scene).render();
+    assert!(framebuffer.sample_counts().iter().all(|count| *count == 4));
+    assert!(framebuffer
+        .pixels()
+        .iter()
+        .all(|color| (color.vec - Vec3(0.5, 2.0, 4.0)).length() < 1e-9));
+}
+
+#[test]
+fn adaptive_sampling_spends_samples_where_there_is_noise() {
+    let scene = Scene::random_spheres(adaptive_settings(), 1);
+    let counts = Renderer::new(&scene).render().sample_counts().to_vec();
+    assert!(counts.iter().all(|count| (4..=64).contains(count)));
+    // the sky along the top converges at once, while the diffuse ground is
+    // noisy
+    assert!(counts[..40].iter().all(|count| *count == 4), "{:?}", counts);
+    assert!(counts.contains(&64), "{:?}", counts);
+    // and without a threshold every pixel gets the same samples
+    let scene = Scene::random_spheres(small_settings(), 1);
+    let counts = Renderer::new(&scene).render().sample_counts().to_vec();
+    assert!(counts.iter().all(|count| *count == 4));
+}
+
+#[test]
+fn adaptive_progressive_rendering_ends_with_the_same_image() {
+    let scene = Scene::random_spheres(adaptive_settings(), 1);
+    let renderer = Renderer::new(&scene);
+    let image = renderer.render();
+    let progressive = renderer.render_progressive(|_, _| {});
+    assert_eq!(pixel_values(&progressive), pixel_values(&image));
+    assert_eq!(progressive.sample_counts(), image.sample_counts());
+}